@@ -34,6 +34,8 @@ async fn register(
         username: payload.username.clone(),
         email: payload.email.clone(),
         password: hashed_password,
+        calendar_token: None,
+        plan: None,
     };
 
     user_collection.insert_one(&new_user, None)
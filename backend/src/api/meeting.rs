@@ -0,0 +1,763 @@
+use axum::{
+    routing::{get, post}, Router, extract::{Path, Query, State}, response::{IntoResponse, Json},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{env, sync::Arc};
+
+use crate::{
+    api::{room::create_room_with_unique_code, user_id_from_headers},
+    db::connection::Database,
+    models::{
+        meeting_model::{Frequency, Meeting, MeetingSeries, Recurrence, Weekday},
+        user_model::User,
+    },
+    utils::ics::{calendar, rrule, CalendarEvent},
+    ws::{close_room, end_room}, SharedState,
+};
+
+// Times are RFC 3339 strings with an offset, e.g. `2025-06-01T09:00:00+02:00`.
+#[derive(Debug, Deserialize)]
+struct CreateMeetingRequest {
+    title: String,
+    #[serde(default)]
+    description: String,
+    start_time: String,
+    end_time: String,
+    timezone: Option<String>,
+    #[serde(default)]
+    invitees: Vec<String>,
+    // Turns the meeting into a series; `start_time` and `end_time` then
+    // describe the first occurrence.
+    recurrence: Option<RecurrenceRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecurrenceRequest {
+    frequency: Frequency,
+    interval: Option<u32>,
+    #[serde(default)]
+    weekdays: Vec<Weekday>,
+    until: Option<String>,
+    count: Option<u32>,
+    #[serde(default)]
+    exceptions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeriesUpdate {
+    title: Option<String>,
+    description: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    timezone: Option<String>,
+    invitees: Option<Vec<String>>,
+    recurrence: Option<RecurrenceRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OccurrenceQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeetingUpdate {
+    title: Option<String>,
+    description: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    timezone: Option<String>,
+    invitees: Option<Vec<String>>,
+}
+
+const MAX_RECURRENCE_INTERVAL: u32 = 365;
+const CALENDAR_TOKEN_LENGTH: usize = 32;
+const DEFAULT_OCCURRENCE_LIMIT: usize = 20;
+const MAX_OCCURRENCE_LIMIT: usize = 100;
+
+pub fn parse_time(value: &str) -> Result<DateTime, StatusCode> {
+    parse_time_with_offset(value).map(|(time, _)| time)
+}
+
+/// Parses an RFC 3339 time, also returning its UTC offset in minutes.
+fn parse_time_with_offset(value: &str) -> Result<(DateTime, i32), StatusCode> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|time| (
+            DateTime::from_millis(time.timestamp_millis()),
+            time.offset().local_minus_utc() / 60,
+        ))
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+impl RecurrenceRequest {
+    fn into_recurrence(self) -> Result<Recurrence, StatusCode> {
+        let interval = self.interval.unwrap_or(1);
+        if !(1..=MAX_RECURRENCE_INTERVAL).contains(&interval)
+            || self.count == Some(0)
+            || (self.frequency != Frequency::Weekly && !self.weekdays.is_empty())
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        Ok(Recurrence {
+            frequency: self.frequency,
+            interval,
+            weekdays: self.weekdays,
+            until: self.until.as_deref().map(parse_time).transpose()?,
+            count: self.count,
+            exceptions: self
+                .exceptions
+                .iter()
+                .map(|exception| parse_time(exception))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+fn time_string(time: DateTime) -> Option<String> {
+    time.try_to_rfc3339_string().ok()
+}
+
+fn normalize_invitees(invitees: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = invitees
+        .into_iter()
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+fn meeting_view(meeting: &Meeting) -> Value {
+    json!({
+        "id": meeting._id.map(|id| id.to_hex()),
+        "code": meeting.room_code,
+        "host_id": meeting.host_id.to_hex(),
+        "title": meeting.title,
+        "description": meeting.description,
+        "start_time": time_string(meeting.start_time),
+        "end_time": time_string(meeting.end_time),
+        "join_opens_at": time_string(meeting.join_opens_at()),
+        "timezone": meeting.timezone,
+        "invitees": meeting.invitees,
+        "series_id": meeting.series_id.map(|id| id.to_hex()),
+    })
+}
+
+fn series_view(series: &MeetingSeries) -> Value {
+    let recurrence = &series.recurrence;
+    json!({
+        "id": series._id.map(|id| id.to_hex()),
+        "code": series.room_code,
+        "host_id": series.host_id.to_hex(),
+        "title": series.title,
+        "description": series.description,
+        "start_time": time_string(series.start_time),
+        "duration_mins": series.duration_mins,
+        "timezone": series.timezone,
+        "invitees": series.invitees,
+        "recurrence": {
+            "frequency": recurrence.frequency,
+            "interval": recurrence.interval,
+            "weekdays": recurrence.weekdays,
+            "until": recurrence.until.and_then(time_string),
+            "count": recurrence.count,
+            "exceptions": recurrence.exceptions.iter().filter_map(|time| time_string(*time)).collect::<Vec<_>>(),
+        },
+    })
+}
+
+/// Link that opens the room in the web client, taken from `APP_BASE_URL`.
+pub fn join_url(room_code: &str) -> String {
+    let base = env::var("APP_BASE_URL").unwrap_or_else(|_| "http://localhost:5173".to_string());
+    format!("{}/room/{}", base.trim_end_matches('/'), room_code)
+}
+
+pub fn meeting_event(meeting: &Meeting, organizer: &User) -> CalendarEvent {
+    CalendarEvent {
+        uid: meeting._id.map(|id| id.to_hex()).unwrap_or_default(),
+        title: meeting.title.clone(),
+        description: meeting.description.clone(),
+        start: meeting.start_time,
+        end: meeting.end_time,
+        join_url: join_url(&meeting.room_code),
+        organizer_name: organizer.username.clone(),
+        organizer_email: organizer.email.clone(),
+        attendees: meeting.invitees.clone(),
+        rrule: None,
+        exdates: Vec::new(),
+    }
+}
+
+pub fn series_event(series: &MeetingSeries, organizer: &User) -> CalendarEvent {
+    CalendarEvent {
+        uid: series._id.map(|id| id.to_hex()).unwrap_or_default(),
+        title: series.title.clone(),
+        description: series.description.clone(),
+        start: series.start_time,
+        end: DateTime::from_millis(series.start_time.timestamp_millis() + series.duration_mins * 60 * 1000),
+        join_url: join_url(&series.room_code),
+        organizer_name: organizer.username.clone(),
+        organizer_email: organizer.email.clone(),
+        attendees: series.invitees.clone(),
+        rrule: Some(rrule(&series.recurrence, series.start_time, series.utc_offset_mins)),
+        exdates: series.recurrence.exceptions.clone(),
+    }
+}
+
+pub fn calendar_response(text: String) -> impl IntoResponse {
+    (StatusCode::OK, [(CONTENT_TYPE, "text/calendar; charset=utf-8")], text)
+}
+
+/// Stores the occurrences of a series that start within `SERIES_HORIZON_DAYS`
+/// (30 by default) and have not finished yet, carrying on after the last one
+/// already stored.
+pub async fn schedule_occurrences(db: Arc<Database>, series: &MeetingSeries) -> mongodb::error::Result<()> {
+    let Some(series_id) = series._id else {
+        return Ok(());
+    };
+    let horizon_days: i64 = env::var("SERIES_HORIZON_DAYS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+
+    let now = DateTime::now();
+    let horizon = DateTime::from_millis(now.timestamp_millis() + horizon_days * 24 * 60 * 60 * 1000);
+    let duration_ms = series.duration_mins * 60 * 1000;
+
+    let stored = Database::get_series_occurrences(db.clone(), series_id).await?;
+    let last_start = stored.iter().filter_map(|meeting| meeting.occurrence_start).max();
+
+    for start in series.recurrence.starts(series.start_time, series.utc_offset_mins, horizon) {
+        let end_time = DateTime::from_millis(start.timestamp_millis() + duration_ms);
+        if last_start.is_some_and(|last| start <= last) || end_time <= now {
+            continue;
+        }
+
+        let occurrence = Meeting {
+            _id: None,
+            room_code: series.room_code.clone(),
+            host_id: series.host_id,
+            title: series.title.clone(),
+            description: series.description.clone(),
+            start_time: start,
+            end_time,
+            timezone: series.timezone.clone(),
+            invitees: series.invitees.clone(),
+            series_id: Some(series_id),
+            occurrence_start: Some(start),
+            warning_sent: false,
+            ended: false,
+            created_at: now,
+        };
+        Database::create_meeting(db.clone(), occurrence).await?;
+    }
+
+    Ok(())
+}
+
+async fn upcoming_occurrences(db: Arc<Database>, series_id: ObjectId) -> Result<Vec<Meeting>, StatusCode> {
+    let occurrences = Database::get_series_occurrences(db, series_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(occurrences.into_iter().filter(|meeting| !meeting.ended).collect())
+}
+
+async fn user_email(state: &SharedState, user_id: ObjectId) -> Result<String, StatusCode> {
+    Database::get_user_by_id(state.db.clone(), user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(|user| user.email)
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+async fn find_series(state: &SharedState, series_id: &str) -> Result<MeetingSeries, StatusCode> {
+    let series_id = ObjectId::parse_str(series_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Database::get_series_by_id(state.db.clone(), series_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn find_meeting(state: &SharedState, meeting_id: &str) -> Result<Meeting, StatusCode> {
+    let meeting_id = ObjectId::parse_str(meeting_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Database::get_meeting_by_id(state.db.clone(), meeting_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn create_meeting(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateMeetingRequest>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let host_id = user_id_from_headers(&headers)?;
+
+    let (start_time, utc_offset_mins) = parse_time_with_offset(&payload.start_time)?;
+    let end_time = parse_time(&payload.end_time)?;
+    if payload.title.trim().is_empty() || end_time <= start_time || end_time <= DateTime::now() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let recurrence = payload.recurrence.map(RecurrenceRequest::into_recurrence).transpose()?;
+
+    // Reserve the room now so the code can go out with the invitation.
+    let room_code = create_room_with_unique_code(&state, host_id).await?;
+
+    if let Some(recurrence) = recurrence {
+        let series = MeetingSeries {
+            _id: None,
+            room_code,
+            host_id,
+            title: payload.title.trim().to_string(),
+            description: payload.description,
+            start_time,
+            duration_mins: (end_time.timestamp_millis() - start_time.timestamp_millis()) / 60_000,
+            timezone: payload.timezone.unwrap_or_else(|| "UTC".to_string()),
+            utc_offset_mins,
+            invitees: normalize_invitees(payload.invitees),
+            recurrence,
+            created_at: DateTime::now(),
+        };
+        let series = Database::create_series(db.clone(), series)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        schedule_occurrences(db.clone(), &series)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let series_id = series._id.ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let occurrences = upcoming_occurrences(db.clone(), series_id).await?;
+
+        return Ok((
+            StatusCode::CREATED,
+            Json(json!({
+                "success": true,
+                "message": "Meeting series scheduled successfully",
+                "series": series_view(&series),
+                "occurrences": occurrences.iter().map(meeting_view).collect::<Vec<_>>()
+            }))
+        ));
+    }
+
+    let meeting = Meeting {
+        _id: None,
+        room_code,
+        host_id,
+        title: payload.title.trim().to_string(),
+        description: payload.description,
+        start_time,
+        end_time,
+        timezone: payload.timezone.unwrap_or_else(|| "UTC".to_string()),
+        invitees: normalize_invitees(payload.invitees),
+        series_id: None,
+        occurrence_start: None,
+        warning_sent: false,
+        ended: false,
+        created_at: DateTime::now(),
+    };
+    let meeting = Database::create_meeting(db.clone(), meeting)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "success": true,
+            "message": "Meeting scheduled successfully",
+            "meeting": meeting_view(&meeting)
+        }))
+    ))
+}
+
+async fn list_meetings(
+    State(state): State<SharedState>,
+    headers: HeaderMap
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+    let email = user_email(&state, user_id).await?;
+
+    let meetings = Database::get_meetings_for_user(db.clone(), user_id, &email.to_lowercase())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let items: Vec<Value> = meetings
+        .iter()
+        .map(|meeting| {
+            let mut view = meeting_view(meeting);
+            view["is_host"] = json!(meeting.host_id == user_id);
+            view
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "meetings": items }))
+    ))
+}
+
+async fn get_meeting(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(meeting_id): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let user_id = user_id_from_headers(&headers)?;
+    let meeting = find_meeting(&state, &meeting_id).await?;
+
+    if meeting.host_id != user_id {
+        let email = user_email(&state, user_id).await?;
+        if !meeting.is_invited(&email) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "meeting": meeting_view(&meeting) }))
+    ))
+}
+
+async fn update_meeting(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(meeting_id): Path<String>,
+    Json(payload): Json<MeetingUpdate>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+    let mut meeting = find_meeting(&state, &meeting_id).await?;
+
+    if meeting.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if meeting.ended {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    if let Some(title) = payload.title {
+        if title.trim().is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        meeting.title = title.trim().to_string();
+    }
+    if let Some(description) = payload.description {
+        meeting.description = description;
+    }
+    if let Some(start_time) = payload.start_time {
+        meeting.start_time = parse_time(&start_time)?;
+    }
+    if let Some(end_time) = payload.end_time {
+        let end_time = parse_time(&end_time)?;
+        // A later end earns a fresh warning.
+        if end_time > meeting.end_time {
+            meeting.warning_sent = false;
+        }
+        meeting.end_time = end_time;
+    }
+    if let Some(timezone) = payload.timezone {
+        meeting.timezone = timezone;
+    }
+    if let Some(invitees) = payload.invitees {
+        meeting.invitees = normalize_invitees(invitees);
+    }
+
+    if meeting.end_time <= meeting.start_time || meeting.end_time <= DateTime::now() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Database::update_meeting(db.clone(), &meeting)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "meeting": meeting_view(&meeting) }))
+    ))
+}
+
+async fn cancel_meeting(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(meeting_id): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+    let meeting = find_meeting(&state, &meeting_id).await?;
+
+    if meeting.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Some(id) = meeting._id {
+        Database::delete_meeting(db.clone(), id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Cancelling one occurrence only closes the room if that occurrence is
+    // open; the series keeps the code for the others.
+    let is_open = DateTime::now() >= meeting.join_opens_at();
+    if let (Some(series_id), Some(start)) = (meeting.series_id, meeting.occurrence_start) {
+        Database::add_series_exception(db.clone(), series_id, start)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    let room = Database::get_room_by_code(db.clone(), &meeting.room_code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(room) = room
+        && (meeting.series_id.is_none() || is_open)
+    {
+        close_room(db.clone(), &state.ws_state, &room, "meeting-cancelled")
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "message": "Meeting cancelled" }))
+    ))
+}
+
+async fn get_series(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(series_id): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let user_id = user_id_from_headers(&headers)?;
+    let series = find_series(&state, &series_id).await?;
+
+    if series.host_id != user_id {
+        let email = user_email(&state, user_id).await?;
+        if !series.invitees.iter().any(|invitee| invitee.eq_ignore_ascii_case(&email)) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "series": series_view(&series) }))
+    ))
+}
+
+async fn list_occurrences(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(series_id): Path<String>,
+    Query(query): Query<OccurrenceQuery>
+) -> Result<impl IntoResponse, StatusCode> {
+    let user_id = user_id_from_headers(&headers)?;
+    let series = find_series(&state, &series_id).await?;
+
+    if series.host_id != user_id {
+        let email = user_email(&state, user_id).await?;
+        if !series.invitees.iter().any(|invitee| invitee.eq_ignore_ascii_case(&email)) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let series_id = series._id.ok_or(StatusCode::NOT_FOUND)?;
+    let limit = query.limit.unwrap_or(DEFAULT_OCCURRENCE_LIMIT).clamp(1, MAX_OCCURRENCE_LIMIT);
+    let occurrences = upcoming_occurrences(state.db.clone(), series_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "occurrences": occurrences.iter().take(limit).map(meeting_view).collect::<Vec<_>>()
+        }))
+    ))
+}
+
+// Edits apply to every occurrence that has not started yet, replacing any
+// single-occurrence changes made to them.
+async fn update_series(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(series_id): Path<String>,
+    Json(payload): Json<SeriesUpdate>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+    let mut series = find_series(&state, &series_id).await?;
+    let series_id = series._id.ok_or(StatusCode::NOT_FOUND)?;
+
+    if series.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Some(title) = payload.title {
+        if title.trim().is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        series.title = title.trim().to_string();
+    }
+    if let Some(description) = payload.description {
+        series.description = description;
+    }
+
+    let mut end_time = DateTime::from_millis(
+        series.start_time.timestamp_millis() + series.duration_mins * 60 * 1000
+    );
+    if let Some(start_time) = payload.start_time {
+        (series.start_time, series.utc_offset_mins) = parse_time_with_offset(&start_time)?;
+        // Keep the length unless a new end is given too.
+        end_time = DateTime::from_millis(
+            series.start_time.timestamp_millis() + series.duration_mins * 60 * 1000
+        );
+    }
+    if let Some(new_end_time) = payload.end_time {
+        end_time = parse_time(&new_end_time)?;
+    }
+    if end_time <= series.start_time {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    series.duration_mins = (end_time.timestamp_millis() - series.start_time.timestamp_millis()) / 60_000;
+
+    if let Some(timezone) = payload.timezone {
+        series.timezone = timezone;
+    }
+    if let Some(invitees) = payload.invitees {
+        series.invitees = normalize_invitees(invitees);
+    }
+    if let Some(recurrence) = payload.recurrence {
+        series.recurrence = recurrence.into_recurrence()?;
+    }
+
+    Database::update_series(db.clone(), &series)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Database::delete_upcoming_occurrences(db.clone(), series_id, DateTime::now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    schedule_occurrences(db.clone(), &series)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let occurrences = upcoming_occurrences(db.clone(), series_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "series": series_view(&series),
+            "occurrences": occurrences.iter().map(meeting_view).collect::<Vec<_>>()
+        }))
+    ))
+}
+
+async fn cancel_series(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(series_id): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+    let series = find_series(&state, &series_id).await?;
+    let series_id = series._id.ok_or(StatusCode::NOT_FOUND)?;
+
+    if series.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Database::delete_series(db.clone(), series_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let room = Database::get_room_by_code(db.clone(), &series.room_code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(room) = room {
+        end_room(db.clone(), &state.ws_state, &room, "meeting-cancelled")
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "message": "Meeting series cancelled" }))
+    ))
+}
+
+// Each call replaces the token, so an old feed URL stops working.
+async fn create_feed_token(
+    State(state): State<SharedState>,
+    headers: HeaderMap
+) -> Result<impl IntoResponse, StatusCode> {
+    let user_id = user_id_from_headers(&headers)?;
+
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CALENDAR_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    Database::set_calendar_token(state.db.clone(), user_id, &token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let base = env::var("API_BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    let feed_url = format!("{}/meeting/feed/{}", base.trim_end_matches('/'), token);
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "feed_url": feed_url }))
+    ))
+}
+
+// Calendar apps cannot send headers, so the token in the path is the only
+// credential. A trailing `.ics` is accepted for clients that expect one.
+async fn calendar_feed(
+    State(state): State<SharedState>,
+    Path(token): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let token = token.strip_suffix(".ics").unwrap_or(&token);
+
+    let user = Database::get_user_by_calendar_token(db.clone(), token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let user_id = user._id.ok_or(StatusCode::NOT_FOUND)?;
+
+    let meetings = Database::get_meetings_for_user(db.clone(), user_id, &user.email.to_lowercase())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut host_ids: Vec<ObjectId> = meetings.iter().map(|meeting| meeting.host_id).collect();
+    host_ids.sort();
+    host_ids.dedup();
+    let hosts = Database::get_users_by_ids(db.clone(), &host_ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let events: Vec<CalendarEvent> = meetings
+        .iter()
+        .filter_map(|meeting| {
+            let host = hosts.iter().find(|host| host._id == Some(meeting.host_id))?;
+            Some(meeting_event(meeting, host))
+        })
+        .collect();
+
+    Ok(calendar_response(calendar(&format!("{}'s meetings", user.username), &events)))
+}
+
+pub fn meeting_router() -> Router<SharedState> {
+    Router::new()
+        .route("/", get(list_meetings))
+        .route("/create", post(create_meeting))
+        .route("/feed-token", post(create_feed_token))
+        .route("/feed/{token}", get(calendar_feed))
+        .route("/{id}", get(get_meeting).patch(update_meeting).delete(cancel_meeting))
+        .route("/series/{id}", get(get_series).patch(update_series).delete(cancel_series))
+        .route("/series/{id}/occurrences", get(list_occurrences))
+}
@@ -1,2 +1,37 @@
+use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+use mongodb::bson::oid::ObjectId;
+
+use crate::utils::jwt::{verify_access_token, verify_guest_token};
+
 pub mod auth;
-pub mod room;
\ No newline at end of file
+pub mod meeting;
+pub mod room;
+
+fn bearer_token(headers: &HeaderMap) -> Result<&str, StatusCode> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+/// Reads the caller's id from an `Authorization: Bearer <access token>` header.
+pub fn user_id_from_headers(headers: &HeaderMap) -> Result<ObjectId, StatusCode> {
+    let claim = verify_access_token(bearer_token(headers)?).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    ObjectId::parse_str(&claim.sub).map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+/// Like `user_id_from_headers`, but also accepts a guest token issued for
+/// `room_code`.
+pub fn member_id_from_headers(headers: &HeaderMap, room_code: &str) -> Result<ObjectId, StatusCode> {
+    let token = bearer_token(headers)?;
+    if let Ok(claim) = verify_access_token(token) {
+        return ObjectId::parse_str(&claim.sub).map_err(|_| StatusCode::BAD_REQUEST);
+    }
+
+    let claim = verify_guest_token(token).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    if !claim.guest || claim.room != room_code {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    ObjectId::parse_str(&claim.sub).map_err(|_| StatusCode::BAD_REQUEST)
+}
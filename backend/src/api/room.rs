@@ -1,14 +1,20 @@
 use axum::{
-    routing::post, Router, extract::State, response::{IntoResponse, Json}, http:: StatusCode,
+    routing::{delete, get, patch, post}, Router, extract::{Path, Query, State}, response::{IntoResponse, Json, Response},
+    http::{header::{CONTENT_DISPOSITION, CONTENT_TYPE}, HeaderMap, StatusCode},
 };
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use rand::Rng;
+use serde_json::{json, Value};
 
 use crate::{
+    api::{meeting::{calendar_response, join_url, meeting_event, parse_time, series_event}, member_id_from_headers, user_id_from_headers},
     db::connection::Database,
-    utils::jwt::verify_access_token, SharedState,
+    models::{message_model::ChatMessage, participant_model::summarize_attendance, room_model::{InviteLink, Role, Room}},
+    utils::{
+        bcrypt::hash_password, ics::calendar,
+        jwt::{generate_guest_token, generate_invite_token, verify_access_token},
+    },
+    ws::{end_room, live_member_count, send_to_room, session_boundary}, SharedState,
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,9 +22,149 @@ struct CreateRequest {
     access_token: String
 }
 
-fn generate_code() -> String {
-    let code: u32 = rand::thread_rng().gen_range(100000..999999);
-    code.to_string()
+#[derive(Debug, Serialize, Deserialize)]
+struct GuestJoinRequest {
+    code: String,
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    page: Option<u64>,
+    limit: Option<i64>,
+}
+
+// Every field is optional so clients can change a single setting. An empty
+// `password` removes the join password and a `max_participants` of 0 removes
+// the limit.
+#[derive(Debug, Deserialize)]
+struct SettingsUpdate {
+    guest_access: Option<bool>,
+    locked: Option<bool>,
+    max_participants: Option<u32>,
+    password: Option<String>,
+    auto_admit_same_domain: Option<bool>,
+    auto_admit_users: Option<Vec<ObjectId>>,
+    chat_enabled: Option<bool>,
+    private_chat_enabled: Option<bool>,
+    remote_control_allowed: Option<bool>,
+    join_muted: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InviteCreate {
+    expires_in_mins: Option<i64>,
+    max_uses: Option<u32>,
+    #[serde(default)]
+    pre_approved: bool,
+    role: Option<Role>,
+}
+
+// `from` and `to` are RFC 3339 times that clip sessions to a window, e.g. one
+// occurrence of a recurring meeting.
+#[derive(Debug, Deserialize)]
+struct AttendanceQuery {
+    format: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+// `before` is the id of the oldest message the client already has.
+#[derive(Debug, Deserialize)]
+struct MessagesQuery {
+    before: Option<String>,
+    limit: Option<i64>,
+}
+
+const MAX_CODE_ATTEMPTS: usize = 5;
+const DEFAULT_INVITE_TTL_MINS: i64 = 7 * 24 * 60;
+const MAX_INVITE_TTL_MINS: i64 = 30 * 24 * 60;
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+fn is_member(room: &Room, user_id: ObjectId) -> bool {
+    room.host_id == user_id || room.participants_id.contains(&user_id)
+}
+
+fn created_at_string(room: &Room) -> Option<String> {
+    room.created_at.and_then(|created_at| created_at.try_to_rfc3339_string().ok())
+}
+
+fn message_view(message: &ChatMessage) -> Value {
+    json!({
+        "id": message._id.map(|id| id.to_hex()),
+        "sender_id": message.sender_id.to_hex(),
+        "username": message.username,
+        "message": message.message,
+        "sent_at": message.sent_at.try_to_rfc3339_string().ok(),
+        "to": message.recipients.iter().map(|id| id.to_hex()).collect::<Vec<_>>(),
+        "edited_at": message.edited_at.and_then(|edited_at| edited_at.try_to_rfc3339_string().ok()),
+        "deleted": message.is_deleted(),
+        "reply_to": message.reply_to.map(|id| id.to_hex()),
+        "reply_count": message.reply_count,
+        "last_reply_at": message.last_reply_at.and_then(|last_reply_at| last_reply_at.try_to_rfc3339_string().ok()),
+        "reactions": message.reactions
+            .iter()
+            .map(|reaction| json!({
+                "emoji": reaction.emoji,
+                "count": reaction.user_ids.len(),
+                "user_ids": reaction.user_ids.iter().map(|id| id.to_hex()).collect::<Vec<_>>(),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+// Pages are fetched newest first with one extra message, which tells whether
+// there is another page, and returned oldest first.
+fn message_page(mut messages: Vec<ChatMessage>, limit: i64) -> Value {
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit as usize);
+    messages.reverse();
+    let next_before = messages.first()
+        .and_then(|message| message._id)
+        .filter(|_| has_more)
+        .map(|id| id.to_hex());
+
+    json!({
+        "success": true,
+        "messages": messages.iter().map(message_view).collect::<Vec<_>>(),
+        "has_more": has_more,
+        "next_before": next_before,
+    })
+}
+
+fn invite_view(room: &Room, invite: &InviteLink) -> Value {
+    let token = generate_invite_token(
+        &invite.id.to_hex(),
+        &room.code,
+        invite.expires_at.timestamp_millis() / 1000
+    );
+    json!({
+        "id": invite.id.to_hex(),
+        "url": format!("{}?invite={}", join_url(&room.code), token),
+        "token": token,
+        "expires_at": invite.expires_at.try_to_rfc3339_string().ok(),
+        "max_uses": invite.max_uses,
+        "uses": invite.uses,
+        "pre_approved": invite.pre_approved,
+        "role": invite.role,
+    })
+}
+
+pub fn settings_view(room: &Room) -> Value {
+    let settings = &room.settings;
+    json!({
+        "guest_access": settings.guest_access,
+        "locked": settings.locked,
+        "max_participants": settings.max_participants,
+        "password_protected": settings.password_hash.is_some(),
+        "auto_admit_same_domain": settings.auto_admit_same_domain,
+        "auto_admit_users": settings.auto_admit_users,
+        "chat_enabled": settings.chat_enabled,
+        "private_chat_enabled": settings.private_chat_enabled,
+        "remote_control_allowed": settings.remote_control_allowed,
+        "join_muted": settings.join_muted,
+    })
 }
 
 async fn create_room(
@@ -26,7 +172,6 @@ async fn create_room(
     Json(payload): Json<CreateRequest>
 ) -> Result<impl IntoResponse, StatusCode> {
 
-    let db = state.db.clone();
 
     let claim = match verify_access_token(&payload.access_token) {
         Ok(claim) => claim,
@@ -41,29 +186,768 @@ async fn create_room(
         Err(_) => return Err(StatusCode::BAD_REQUEST),
     };
 
-    let code = generate_code();
-
-    match Database::create_room(db.clone(), host_id, code.clone()).await {
-        Ok(_) => {
-            // Add the host as a participant
-            match Database::add_participant(db.clone(), code.clone(), host_id).await {
-                Ok(_) => Ok((
-                    StatusCode::CREATED, 
-                    Json(json!({
-                        "success": true,
-                        "message": "Room created successfully",
-                        "code": code
-                    }))
-                )),
-                Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    let code = create_room_with_unique_code(&state, host_id).await?;
+
+    Ok((
+        StatusCode::CREATED, 
+        Json(json!({
+            "success": true,
+            "message": "Room created successfully",
+            "code": code
+        }))
+    ))
+}
+
+/// Creates a room hosted by `host_id` under a freshly generated code, refusing
+/// with `403` once the host's plan allows no more concurrent rooms.
+pub async fn create_room_with_unique_code(
+    state: &SharedState,
+    host_id: ObjectId
+) -> Result<String, StatusCode> {
+    let db = state.db.clone();
+
+    let host = Database::get_user_by_id(db.clone(), host_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if let Some(max_rooms) = state.ws_state.plans.plan_for(&host).max_concurrent_rooms {
+        let hosted = Database::count_hosted_rooms(db.clone(), host_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if hosted >= max_rooms as u64 {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    // The unique index on `rooms.code` rejects collisions, so draw a new code
+    // and try again when the generated one is already taken.
+    let mut created = None;
+    for _ in 0..MAX_CODE_ATTEMPTS {
+        let code = state.code_generator.generate();
+        match Database::create_room(db.clone(), host_id, code.clone()).await {
+            Ok(_) => {
+                created = Some(code);
+                break;
+            }
+            Err(err) if Database::is_duplicate_key(&err) => continue,
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    created.ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+// Guests have no `User` row, so they get a fresh id and a token that is only
+// valid for `join-room` on this one room.
+async fn join_as_guest(
+    State(state): State<SharedState>,
+    Json(payload): Json<GuestJoinRequest>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+
+    let username = payload.username.trim();
+    if username.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let room = Database::get_room_by_code(db.clone(), &payload.code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !room.settings.guest_access {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let guest_id = ObjectId::new().to_hex();
+    let guest_token = generate_guest_token(&guest_id, username, &room.code);
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "message": "Guest token issued",
+            "guest_id": guest_id,
+            "guest_token": guest_token
+        }))
+    ))
+}
+
+async fn list_rooms(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let skip = (page - 1) * limit as u64;
+
+    let (rooms, total) = Database::get_rooms_for_user(db.clone(), user_id, skip, limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut items: Vec<Value> = Vec::with_capacity(rooms.len());
+    for room in &rooms {
+        let live_participants = live_member_count(&state.ws_state, room).await;
+        items.push(json!({
+            "code": room.code,
+            "host_id": room.host_id.to_hex(),
+            "is_host": room.host_id == user_id,
+            "participant_count": room.participants_id.len() + 1,
+            "live_participants": live_participants,
+            "created_at": created_at_string(room),
+        }));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "rooms": items,
+            "page": page,
+            "limit": limit,
+            "total": total
+        }))
+    ))
+}
+
+async fn get_room(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !is_member(&room, user_id) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut member_ids = room.participants_id.clone();
+    member_ids.push(room.host_id);
+    let usernames = Database::get_usernames(db.clone(), &member_ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Guests have no `User` row; fall back to the display name they joined with.
+    let guests = state.ws_state.guests.lock().await.clone();
+    let username_of = |id: &ObjectId| -> String {
+        usernames
+            .get(id)
+            .or_else(|| guests.get(id))
+            .cloned()
+            .unwrap_or_else(|| "Guest".to_string())
+    };
+
+    let participants: Vec<Value> = room
+        .participants_id
+        .iter()
+        .map(|id| json!({
+            "id": id.to_hex(),
+            "username": username_of(id),
+            "guest": guests.contains_key(id),
+            "role": room.role_of(id),
+        }))
+        .collect();
+
+    let live_participants = live_member_count(&state.ws_state, &room).await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "room": {
+                "code": room.code,
+                "host": {
+                    "id": room.host_id.to_hex(),
+                    "username": username_of(&room.host_id),
+                },
+                "participants": participants,
+                "live_participants": live_participants,
+                "settings": settings_view(&room),
+                "created_at": created_at_string(&room),
             }
+        }))
+    ))
+}
+
+async fn update_settings(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+    Json(payload): Json<SettingsUpdate>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let mut room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if room.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let settings = &mut room.settings;
+    if let Some(guest_access) = payload.guest_access {
+        settings.guest_access = guest_access;
+    }
+    if let Some(locked) = payload.locked {
+        settings.locked = locked;
+    }
+    if let Some(max_participants) = payload.max_participants {
+        settings.max_participants = (max_participants > 0).then_some(max_participants);
+    }
+    if let Some(password) = payload.password {
+        settings.password_hash = if password.is_empty() {
+            None
+        } else {
+            Some(hash_password(&password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?)
+        };
+    }
+    if let Some(auto_admit_same_domain) = payload.auto_admit_same_domain {
+        settings.auto_admit_same_domain = auto_admit_same_domain;
+    }
+    if let Some(auto_admit_users) = payload.auto_admit_users {
+        settings.auto_admit_users = auto_admit_users;
+    }
+    if let Some(chat_enabled) = payload.chat_enabled {
+        settings.chat_enabled = chat_enabled;
+    }
+    if let Some(private_chat_enabled) = payload.private_chat_enabled {
+        settings.private_chat_enabled = private_chat_enabled;
+    }
+    if let Some(remote_control_allowed) = payload.remote_control_allowed {
+        settings.remote_control_allowed = remote_control_allowed;
+    }
+    if let Some(join_muted) = payload.join_muted {
+        settings.join_muted = join_muted;
+    }
+
+    Database::update_room_settings(db.clone(), &code, &room.settings)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let settings = settings_view(&room);
+    let response_text = json!({
+        "message_type": "room-settings-updated",
+        "code": room.code,
+        "settings": settings,
+    })
+    .to_string();
+    send_to_room(&state.ws_state, &room, &response_text).await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "settings": settings }))
+    ))
+}
+
+async fn delete_room(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if room.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Meetings that have not ended still point at this code; they have to be
+    // cancelled first.
+    let scheduled = Database::get_meeting_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if scheduled.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    end_room(db.clone(), &state.ws_state, &room, "deleted-by-host")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "message": "Room deleted successfully" }))
+    ))
+}
+
+async fn create_invite(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+    Json(payload): Json<InviteCreate>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if room.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let ttl_mins = payload.expires_in_mins.unwrap_or(DEFAULT_INVITE_TTL_MINS);
+    if !(1..=MAX_INVITE_TTL_MINS).contains(&ttl_mins)
+        || payload.max_uses == Some(0)
+        || payload.role == Some(Role::Host)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = DateTime::now();
+    let invite = InviteLink {
+        id: ObjectId::new(),
+        created_by: user_id,
+        created_at: now,
+        expires_at: DateTime::from_millis(now.timestamp_millis() + ttl_mins * 60 * 1000),
+        max_uses: payload.max_uses,
+        uses: 0,
+        pre_approved: payload.pre_approved,
+        role: payload.role,
+    };
+    Database::add_invite(db.clone(), &code, &invite)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "success": true, "invite": invite_view(&room, &invite) }))
+    ))
+}
+
+async fn list_invites(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if room.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let invites: Vec<Value> = room
+        .invites
+        .iter()
+        .map(|invite| invite_view(&room, invite))
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "invites": invites }))
+    ))
+}
+
+async fn revoke_invite(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path((code, invite_id)): Path<(String, String)>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+    let invite_id = ObjectId::parse_str(&invite_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if room.host_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let removed = Database::remove_invite(db.clone(), &code, invite_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !removed {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "message": "Invite revoked" }))
+    ))
+}
+
+fn time_string(time: Option<DateTime>) -> Option<String> {
+    time.and_then(|time| time.try_to_rfc3339_string().ok())
+}
+
+// Spreadsheets evaluate cells starting with these as formulas, so they get a
+// leading `'` to be shown as text.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+// Codes are reused, so the host of a live room only sees its current session.
+// Anyone else, e.g. the host of a one-off meeting whose room is gone, can read
+// an archived session they hosted, clipped to when it ran.
+async fn attendance(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+    Query(query): Query<AttendanceQuery>
+) -> Result<Response, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let from = query.from.as_deref().map(parse_time).transpose()?;
+    let to = query.to.as_deref().map(parse_time).transpose()?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (since, from, to) = match room {
+        Some(room) if room.host_id == user_id => {
+            let since = session_boundary(&db, &room)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            (since, from.max(since), to)
+        }
+        _ => {
+            let session = Database::get_hosted_session(db.clone(), &code, user_id, from, to)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::FORBIDDEN)?;
+            let to = to.map_or(session.ended_at, |to| to.min(session.ended_at));
+            (Some(session.started_at), from.max(Some(session.started_at)), Some(to))
         }
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let records = Database::get_attendance(db.clone(), &code, since, from, to)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let summaries = summarize_attendance(&records, from, to);
+
+    if query.format.as_deref() == Some("csv") {
+        let mut csv = String::from("user_id,username,guest,sessions,first_joined_at,last_left_at,total_duration_secs\r\n");
+        for summary in &summaries {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\r\n",
+                summary.user_id.to_hex(),
+                csv_field(&summary.username),
+                summary.guest,
+                summary.sessions,
+                time_string(Some(summary.first_joined_at)).unwrap_or_default(),
+                time_string(summary.last_left_at).unwrap_or_default(),
+                summary.total_duration_secs
+            ));
+        }
+
+        let disposition = format!("attachment; filename=\"attendance-{}.csv\"", code);
+        return Ok((
+            StatusCode::OK,
+            [(CONTENT_TYPE, "text/csv; charset=utf-8".to_string()), (CONTENT_DISPOSITION, disposition)],
+            csv
+        ).into_response());
+    }
+
+    let attendees: Vec<Value> = summaries
+        .iter()
+        .map(|summary| json!({
+            "user_id": summary.user_id.to_hex(),
+            "username": summary.username,
+            "guest": summary.guest,
+            "present": summary.last_left_at.is_none(),
+            "first_joined_at": time_string(Some(summary.first_joined_at)),
+            "last_left_at": time_string(summary.last_left_at),
+            "total_duration_secs": summary.total_duration_secs,
+            "sessions": records
+                .iter()
+                .filter(|session| session.user_id == summary.user_id)
+                .map(|session| json!({
+                    "joined_at": time_string(session.joined_at),
+                    "left_at": time_string(session.left_at),
+                    "device": session.device,
+                    "leave_reason": session.leave_reason,
+                }))
+                .collect::<Vec<_>>(),
+        }))
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "code": code, "attendees": attendees }))
+    ).into_response())
+}
+
+async fn messages(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+    Query(query): Query<MessagesQuery>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = member_id_from_headers(&headers, &code)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !is_member(&room, user_id) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let before = query.before.as_deref()
+        .map(ObjectId::parse_str)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let messages = Database::get_messages(db.clone(), &code, room.created_at, before, limit + 1)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::OK, Json(message_page(messages, limit))))
+}
+
+// Only the caller's own conversations: messages they sent or received.
+async fn private_messages(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+    Query(query): Query<MessagesQuery>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = member_id_from_headers(&headers, &code)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !is_member(&room, user_id) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let before = query.before.as_deref()
+        .map(ObjectId::parse_str)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let messages = Database::get_private_messages(
+        db.clone(), &code, user_id, room.created_at, before, limit + 1
+    )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::OK, Json(message_page(messages, limit))))
+}
+
+async fn thread(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path((code, id)): Path<(String, String)>,
+    Query(query): Query<MessagesQuery>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = member_id_from_headers(&headers, &code)?;
+    let parent_id = ObjectId::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !is_member(&room, user_id) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let parent = Database::get_message(db.clone(), parent_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|parent| parent.room_code == code && parent.reply_to.is_none())
+        .filter(|parent| !parent.is_private())
+        .filter(|parent| room.created_at.is_none_or(|since| parent.sent_at >= since))
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let before = query.before.as_deref()
+        .map(ObjectId::parse_str)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut replies = Database::get_thread(db.clone(), parent_id, before, limit + 1)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let has_more = replies.len() as i64 > limit;
+    replies.truncate(limit as usize);
+    replies.reverse();
+    let next_before = replies.first()
+        .and_then(|reply| reply._id)
+        .filter(|_| has_more)
+        .map(|id| id.to_hex());
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "parent": message_view(&parent),
+            "replies": replies.iter().map(message_view).collect::<Vec<_>>(),
+            "has_more": has_more,
+            "next_before": next_before,
+        }))
+    ))
+}
+
+// Everyone's newest read message, for "seen by" lists, and how many of the
+// room's messages the caller hasn't read yet.
+async fn read_markers(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = member_id_from_headers(&headers, &code)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !is_member(&room, user_id) {
+        return Err(StatusCode::FORBIDDEN);
     }
+
+    let markers = Database::get_read_markers(db.clone(), &code, room.created_at)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let read_up_to = markers.iter()
+        .find(|marker| marker.user_id == user_id)
+        .map(|marker| marker.message_id);
+    let unread_count = Database::count_unread(db.clone(), &code, user_id, room.created_at, read_up_to)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let markers: Vec<Value> = markers
+        .iter()
+        .map(|marker| json!({
+            "user_id": marker.user_id.to_hex(),
+            "message_id": marker.message_id.to_hex(),
+            "read_at": time_string(Some(marker.read_at)),
+        }))
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "success": true, "read_markers": markers, "unread_count": unread_count }))
+    ))
 }
 
+// Recurring series are exported as one event with an RRULE; single
+// occurrence edits are not reflected.
+async fn invite_ics(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(code): Path<String>
+) -> Result<impl IntoResponse, StatusCode> {
+    let db = state.db.clone();
+    let user_id = user_id_from_headers(&headers)?;
+
+    let room = Database::get_room_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let host = Database::get_user_by_id(db.clone(), room.host_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let user = Database::get_user_by_id(db.clone(), user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let series = Database::get_series_by_code(db.clone(), &code)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (event, invitees) = match series {
+        Some(series) => (series_event(&series, &host), series.invitees),
+        None => {
+            let meeting = Database::get_meeting_by_code(db.clone(), &code)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            (meeting_event(&meeting, &host), meeting.invitees)
+        }
+    };
+
+    let invited = invitees.iter().any(|invitee| invitee.eq_ignore_ascii_case(&user.email));
+    if !is_member(&room, user_id) && !invited {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let title = event.title.clone();
+    Ok(calendar_response(calendar(&title, &[event])))
+}
 
 pub fn room_router() -> Router<SharedState> {
     Router::new()
+        .route("/", get(list_rooms))
         .route("/create", post(create_room))
-}
\ No newline at end of file
+        .route("/guest", post(join_as_guest))
+        .route("/{code}", get(get_room).delete(delete_room))
+        .route("/{code}/settings", patch(update_settings))
+        .route("/{code}/invite.ics", get(invite_ics))
+        .route("/{code}/attendance", get(attendance))
+        .route("/{code}/messages", get(messages))
+        .route("/{code}/messages/{id}/thread", get(thread))
+        .route("/{code}/private-messages", get(private_messages))
+        .route("/{code}/read-markers", get(read_markers))
+        .route("/{code}/invites", get(list_invites).post(create_invite))
+        .route("/{code}/invites/{id}", delete(revoke_invite))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("Ada"), "Ada");
+        assert_eq!(csv_field("Lovelace, Ada"), "\"Lovelace, Ada\"");
+        assert_eq!(csv_field("Ada \"Countess\""), "\"Ada \"\"Countess\"\"\"");
+    }
+
+    #[test]
+    fn csv_fields_never_start_a_formula() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("a=b"), "a=b");
+    }
+}
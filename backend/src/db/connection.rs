@@ -1,16 +1,35 @@
+use futures_util::TryStreamExt;
 use mongodb::{
-    Client, Collection,
-    bson::{doc, oid::ObjectId},
-    error::Result,
+    Client, Collection, IndexModel,
+    bson::{DateTime, doc, oid::ObjectId, to_bson},
+    error::{Error, ErrorKind, Result, WriteFailure},
+    options::{
+        FindOneAndUpdateOptions, FindOneOptions, FindOptions, IndexOptions, ReturnDocument,
+        UpdateOptions,
+    },
 };
-use std::{env, sync::Arc};
+use std::{collections::HashMap, env, sync::Arc};
 
-use crate::models::{participant_model::Participant, room_model::Room, user_model::User};
+use crate::models::{
+    history_model::RoomHistory,
+    meeting_model::{Meeting, MeetingSeries},
+    message_model::{ChatMessage, MessageEdit, ReadMarker},
+    participant_model::Participant,
+    room_model::{BanEntry, InviteLink, LobbyEntry, Role, RoleAssignment, Room, RoomSettings},
+    user_model::User,
+};
 
 pub struct Database {
     pub user: Collection<User>,
     pub room: Collection<Room>,
     pub participant: Collection<Participant>,
+    pub meeting: Collection<Meeting>,
+    pub series: Collection<MeetingSeries>,
+    pub history: Collection<RoomHistory>,
+    pub message: Collection<ChatMessage>,
+    pub private_message: Collection<ChatMessage>,
+    pub read_marker: Collection<ReadMarker>,
+    pub private_read_marker: Collection<ReadMarker>,
 }
 
 impl Database {
@@ -24,14 +43,85 @@ impl Database {
         let user: Collection<User> = db.collection("users");
         let room: Collection<Room> = db.collection("rooms");
         let participant: Collection<Participant> = db.collection("participants");
+        let meeting: Collection<Meeting> = db.collection("meetings");
+        let series: Collection<MeetingSeries> = db.collection("meeting_series");
+        let history: Collection<RoomHistory> = db.collection("room_history");
+        let message: Collection<ChatMessage> = db.collection("messages");
+        let private_message: Collection<ChatMessage> = db.collection("private_messages");
+        let read_marker: Collection<ReadMarker> = db.collection("read_markers");
+        let private_read_marker: Collection<ReadMarker> = db.collection("private_read_markers");
+
+        let code_index = IndexModel::builder()
+            .keys(doc! { "code": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(err) = room.create_index(code_index, None).await {
+            eprintln!("❌ Failed to create unique index on rooms.code: {}", err);
+        }
+
+        let transcript_index = IndexModel::builder()
+            .keys(doc! { "room_code": 1, "_id": -1 })
+            .build();
+        if let Err(err) = message.create_index(transcript_index, None).await {
+            eprintln!("❌ Failed to create index on messages.room_code: {}", err);
+        }
+
+        let private_index = IndexModel::builder()
+            .keys(doc! { "room_code": 1, "_id": -1 })
+            .build();
+        if let Err(err) = private_message.create_index(private_index, None).await {
+            eprintln!(
+                "❌ Failed to create index on private_messages.room_code: {}",
+                err
+            );
+        }
+
+        let marker_index = IndexModel::builder()
+            .keys(doc! { "room_code": 1, "user_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(err) = read_marker.create_index(marker_index, None).await {
+            eprintln!(
+                "❌ Failed to create unique index on read_markers.room_code, user_id: {}",
+                err
+            );
+        }
+
+        let private_marker_index = IndexModel::builder()
+            .keys(doc! { "room_code": 1, "user_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(err) = private_read_marker
+            .create_index(private_marker_index, None)
+            .await
+        {
+            eprintln!(
+                "❌ Failed to create unique index on private_read_markers.room_code, user_id: {}",
+                err
+            );
+        }
 
         Ok(Database {
             user,
             room,
             participant,
+            meeting,
+            series,
+            history,
+            message,
+            private_message,
+            read_marker,
+            private_read_marker,
         })
     }
 
+    pub fn is_duplicate_key(err: &Error) -> bool {
+        matches!(
+            err.kind.as_ref(),
+            ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == 11000
+        )
+    }
+
     pub async fn get_user_by_id(
         db: Arc<Database>,
         user_id: ObjectId,
@@ -42,6 +132,28 @@ impl Database {
         Ok(user)
     }
 
+    pub async fn get_users_by_ids(
+        db: Arc<Database>,
+        user_ids: &[ObjectId],
+    ) -> mongodb::error::Result<Vec<User>> {
+        let filter = doc! { "_id": { "$in": user_ids } };
+        let users = db.user.find(filter, None).await?.try_collect().await?;
+
+        Ok(users)
+    }
+
+    pub async fn get_usernames(
+        db: Arc<Database>,
+        user_ids: &[ObjectId],
+    ) -> mongodb::error::Result<HashMap<ObjectId, String>> {
+        let users = Database::get_users_by_ids(db, user_ids).await?;
+
+        Ok(users
+            .into_iter()
+            .filter_map(|user| user._id.map(|id| (id, user.username)))
+            .collect())
+    }
+
     pub async fn create_room(
         db: Arc<Database>,
         host_id: ObjectId,
@@ -52,12 +164,119 @@ impl Database {
             host_id,
             code,
             participants_id: vec![],
+            created_at: Some(DateTime::now()),
+            settings: RoomSettings::default(),
+            lobby: vec![],
+            roles: vec![],
+            banned: vec![],
+            invites: vec![],
+            parent_code: None,
+            breakout_name: None,
+            breakouts_end_at: None,
         };
 
         db.room.insert_one(new_room, None).await?;
         Ok(())
     }
 
+    /// Creates an empty breakout of `parent`, sharing its host, co-hosts and
+    /// settings.
+    pub async fn create_breakout(
+        db: Arc<Database>,
+        parent: &Room,
+        code: String,
+        name: String,
+    ) -> mongodb::error::Result<()> {
+        let breakout = Room {
+            _id: Some(ObjectId::new()),
+            host_id: parent.host_id,
+            code,
+            participants_id: vec![],
+            created_at: Some(DateTime::now()),
+            settings: parent.settings.clone(),
+            lobby: vec![],
+            roles: parent.roles.clone(),
+            banned: vec![],
+            invites: vec![],
+            parent_code: Some(parent.code.clone()),
+            breakout_name: Some(name),
+            breakouts_end_at: None,
+        };
+
+        db.room.insert_one(breakout, None).await?;
+        Ok(())
+    }
+
+    pub async fn get_breakouts(
+        db: Arc<Database>,
+        parent_code: &str,
+    ) -> mongodb::error::Result<Vec<Room>> {
+        let filter = doc! { "parent_code": parent_code };
+        let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+        db.room.find(filter, options).await?.try_collect().await
+    }
+
+    pub async fn get_breakout_for_member(
+        db: Arc<Database>,
+        parent_code: &str,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<Option<Room>> {
+        let filter = doc! { "parent_code": parent_code, "participants_id": user_id };
+        db.room.find_one(filter, None).await
+    }
+
+    /// Moves a member into one breakout, taking them out of any other breakout
+    /// of the same parent.
+    pub async fn assign_to_breakout(
+        db: Arc<Database>,
+        parent_code: &str,
+        breakout_code: &str,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        Database::remove_from_breakouts(db.clone(), parent_code, user_id).await?;
+        db.room
+            .update_one(
+                doc! { "code": breakout_code, "parent_code": parent_code },
+                doc! { "$addToSet": { "participants_id": user_id } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_breakouts(
+        db: Arc<Database>,
+        parent_code: &str,
+    ) -> mongodb::error::Result<()> {
+        db.room
+            .delete_many(doc! { "parent_code": parent_code }, None)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_breakouts_end(
+        db: Arc<Database>,
+        room_code: &str,
+        ends_at: Option<DateTime>,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code };
+        let update = match ends_at {
+            Some(ends_at) => doc! { "$set": { "breakouts_end_at": ends_at } },
+            None => doc! { "$unset": { "breakouts_end_at": "" } },
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn get_rooms_with_breakouts_due(
+        db: Arc<Database>,
+        now: DateTime,
+    ) -> mongodb::error::Result<Vec<Room>> {
+        let filter = doc! { "breakouts_end_at": { "$lte": now } };
+        db.room.find(filter, None).await?.try_collect().await
+    }
+
     pub async fn get_room_by_code(
         db: Arc<Database>,
         room_code: &str,
@@ -68,6 +287,28 @@ impl Database {
         Ok(room)
     }
 
+    pub async fn get_rooms_for_user(
+        db: Arc<Database>,
+        user_id: ObjectId,
+        skip: u64,
+        limit: i64,
+    ) -> mongodb::error::Result<(Vec<Room>, u64)> {
+        let filter = doc! {
+            "$or": [ { "host_id": user_id }, { "participants_id": user_id } ],
+            "parent_code": null
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .skip(skip)
+            .limit(limit)
+            .build();
+
+        let total = db.room.count_documents(filter.clone(), None).await?;
+        let rooms = db.room.find(filter, options).await?.try_collect().await?;
+
+        Ok((rooms, total))
+    }
+
     pub async fn add_participant_to_room(
         db: Arc<Database>,
         room_code: &str,
@@ -94,40 +335,1060 @@ impl Database {
         Ok(())
     }
 
+    /// Makes `new_host_id` the host and drops them from the participant list
+    /// and role assignments in the same update. When `previous_host` is given,
+    /// the old host stays in the room with that role and the update only
+    /// applies while they are still the host. Returns whether the room changed.
     pub async fn update_host_id(
         db: Arc<Database>,
         room_code: &str,
         new_host_id: ObjectId,
+        previous_host: Option<(ObjectId, Role)>,
+    ) -> mongodb::error::Result<bool> {
+        let mut filter = doc! { "code": room_code };
+        let mut participants = doc! {
+            "$filter": {
+                "input": { "$ifNull": ["$participants_id", []] },
+                "cond": { "$ne": ["$$this", new_host_id] }
+            }
+        };
+        let mut roles = doc! {
+            "$filter": {
+                "input": { "$ifNull": ["$roles", []] },
+                "cond": { "$ne": ["$$this.user_id", new_host_id] }
+            }
+        };
+
+        if let Some((previous_host_id, role)) = previous_host {
+            filter.insert("host_id", previous_host_id);
+            participants = doc! { "$concatArrays": [participants, [previous_host_id]] };
+            if role != Role::Attendee {
+                let assignment = to_bson(&RoleAssignment {
+                    user_id: previous_host_id,
+                    role,
+                })?;
+                roles = doc! { "$concatArrays": [roles, [assignment]] };
+            }
+        }
+
+        let update = vec![doc! {
+            "$set": {
+                "host_id": new_host_id,
+                "participants_id": participants,
+                "roles": roles
+            }
+        }];
+
+        let result = db.room.update_one(filter, update, None).await?;
+        if result.modified_count != 1 {
+            return Ok(false);
+        }
+
+        // Breakouts follow their parent's host.
+        db.room
+            .update_many(
+                doc! { "parent_code": room_code },
+                doc! {
+                    "$set": { "host_id": new_host_id },
+                    "$pull": { "participants_id": new_host_id }
+                },
+                None,
+            )
+            .await?;
+
+        Ok(true)
+    }
+
+    pub async fn remove_from_breakouts(
+        db: Arc<Database>,
+        parent_code: &str,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        db.room
+            .update_many(
+                doc! { "parent_code": parent_code },
+                doc! { "$pull": { "participants_id": user_id } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_room_settings(
+        db: Arc<Database>,
+        room_code: &str,
+        settings: &RoomSettings,
     ) -> mongodb::error::Result<()> {
         let filter = doc! { "code": room_code };
         let update = doc! {
-            "$set": { "host_id": new_host_id }
+            "$set": { "settings": to_bson(settings)? }
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    /// Queues `entry` in the room's lobby. A user already waiting keeps their
+    /// original place.
+    pub async fn add_to_lobby(
+        db: Arc<Database>,
+        room_code: &str,
+        entry: LobbyEntry,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code, "lobby.user_id": { "$ne": entry.user_id } };
+        let update = doc! {
+            "$push": { "lobby": to_bson(&entry)? }
         };
 
         db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn remove_from_lobby(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code };
+        let update = doc! {
+            "$pull": { "lobby": { "user_id": user_id } }
+        };
 
+        db.room.update_one(filter, update, None).await?;
         Ok(())
     }
 
+    pub async fn get_room_by_lobby_user(
+        db: Arc<Database>,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<Option<Room>> {
+        let filter = doc! { "lobby.user_id": user_id };
+        let room = db.room.find_one(filter, None).await?;
+
+        Ok(room)
+    }
+
+    pub async fn clear_lobby(db: Arc<Database>, room_code: &str) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code };
+        let update = doc! {
+            "$set": { "lobby": [] }
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    /// Drops lobby entries requested before `cutoff`, returning the rooms as
+    /// they were before the entries were removed.
+    pub async fn expire_lobby_entries(
+        db: Arc<Database>,
+        cutoff: DateTime,
+    ) -> mongodb::error::Result<Vec<Room>> {
+        let filter = doc! { "lobby.requested_at": { "$lt": cutoff } };
+        let rooms: Vec<Room> = db
+            .room
+            .find(filter.clone(), None)
+            .await?
+            .try_collect()
+            .await?;
+
+        let update = doc! {
+            "$pull": { "lobby": { "requested_at": { "$lt": cutoff } } }
+        };
+        db.room.update_many(filter, update, None).await?;
+
+        Ok(rooms)
+    }
+
+    /// Records `role` for a member. Attendee is the default, so it is stored
+    /// by removing any existing assignment.
+    pub async fn set_role(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+        role: Role,
+    ) -> mongodb::error::Result<()> {
+        Database::remove_role(db.clone(), room_code, user_id).await?;
+
+        if role != Role::Attendee {
+            let filter = doc! { "code": room_code };
+            let update = doc! {
+                "$push": { "roles": to_bson(&RoleAssignment { user_id, role })? }
+            };
+            db.room.update_one(filter, update, None).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn remove_role(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code };
+        let update = doc! {
+            "$pull": { "roles": { "user_id": user_id } }
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn ban_user(
+        db: Arc<Database>,
+        room_code: &str,
+        entry: BanEntry,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code, "banned.user_id": { "$ne": entry.user_id } };
+        let update = doc! {
+            "$push": { "banned": to_bson(&entry)? }
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn unban_user(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code };
+        let update = doc! {
+            "$pull": { "banned": { "user_id": user_id } }
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn count_hosted_rooms(
+        db: Arc<Database>,
+        host_id: ObjectId,
+    ) -> mongodb::error::Result<u64> {
+        let filter = doc! { "host_id": host_id, "parent_code": null };
+        db.room.count_documents(filter, None).await
+    }
+
     pub async fn delete_room(db: Arc<Database>, room_code: &str) -> mongodb::error::Result<()> {
         let filter = doc! { "code": room_code };
         db.room.delete_one(filter, None).await?;
         Ok(())
     }
 
-    pub async fn add_participant(
+    /// Opens an attendance session, closing any the user still had open in
+    /// the room's current session, which began at `since`.
+    pub async fn record_join(
+        db: Arc<Database>,
+        session: Participant,
+        since: Option<DateTime>,
+    ) -> mongodb::error::Result<()> {
+        Database::record_leave(
+            db.clone(),
+            &session.room_code,
+            session.user_id,
+            since,
+            "rejoined",
+        )
+        .await?;
+
+        let session = Participant {
+            _id: Some(ObjectId::new()),
+            joined_at: Some(DateTime::now()),
+            left_at: None,
+            leave_reason: None,
+            ..session
+        };
+        db.participant.insert_one(session, None).await?;
+
+        Ok(())
+    }
+
+    /// Closes the user's open attendance in the room's current session.
+    /// Sessions left open by an earlier room under the same code are not
+    /// touched, so they are never stretched up to now.
+    pub async fn record_leave(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+        since: Option<DateTime>,
+        reason: &str,
+    ) -> mongodb::error::Result<()> {
+        let mut filter = doc! { "room_code": room_code, "user_id": user_id, "left_at": null };
+        if let Some(since) = since {
+            filter.insert("joined_at", doc! { "$gte": since });
+        }
+        let update = doc! {
+            "$set": { "left_at": DateTime::now(), "leave_reason": reason }
+        };
+
+        db.participant.update_many(filter, update, None).await?;
+        Ok(())
+    }
+
+    /// Closes every open session since `since` in a room, e.g. when it ends.
+    pub async fn close_attendance(
+        db: Arc<Database>,
+        room_code: &str,
+        since: Option<DateTime>,
+        reason: &str,
+    ) -> mongodb::error::Result<()> {
+        let mut filter = doc! { "room_code": room_code, "left_at": null };
+        if let Some(since) = since {
+            filter.insert("joined_at", doc! { "$gte": since });
+        }
+        let update = doc! {
+            "$set": { "left_at": DateTime::now(), "leave_reason": reason }
+        };
+
+        db.participant.update_many(filter, update, None).await?;
+        Ok(())
+    }
+
+    /// Sessions in a room that began at `since` or later and overlap
+    /// `from`..`to`, oldest first. `since` keeps out earlier rooms that used the
+    /// same code, including sessions they never closed.
+    pub async fn get_attendance(
+        db: Arc<Database>,
+        room_code: &str,
+        since: Option<DateTime>,
+        from: Option<DateTime>,
+        to: Option<DateTime>,
+    ) -> mongodb::error::Result<Vec<Participant>> {
+        let mut joined_at = doc! { "$ne": null };
+        if let Some(since) = since {
+            joined_at.insert("$gte", since);
+        }
+        if let Some(to) = to {
+            joined_at.insert("$lte", to);
+        }
+        let mut filter = doc! { "room_code": room_code, "joined_at": joined_at };
+        if let Some(from) = from {
+            filter.insert(
+                "$or",
+                vec![
+                    doc! { "left_at": null },
+                    doc! { "left_at": { "$gte": from } },
+                ],
+            );
+        }
+        let options = FindOptions::builder().sort(doc! { "joined_at": 1 }).build();
+
+        db.participant
+            .find(filter, options)
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// The latest archived session under `room_code` that `host_id` hosted and
+    /// that overlaps `from`..`to`.
+    pub async fn get_hosted_session(
+        db: Arc<Database>,
+        room_code: &str,
+        host_id: ObjectId,
+        from: Option<DateTime>,
+        to: Option<DateTime>,
+    ) -> mongodb::error::Result<Option<RoomHistory>> {
+        let mut filter = doc! { "room_code": room_code, "host_id": host_id };
+        if let Some(from) = from {
+            filter.insert("ended_at", doc! { "$gte": from });
+        }
+        if let Some(to) = to {
+            filter.insert("started_at", doc! { "$lte": to });
+        }
+        let options = FindOneOptions::builder()
+            .sort(doc! { "ended_at": -1 })
+            .build();
+
+        db.history.find_one(filter, options).await
+    }
+
+    pub async fn create_meeting(
+        db: Arc<Database>,
+        meeting: Meeting,
+    ) -> mongodb::error::Result<Meeting> {
+        let meeting = Meeting {
+            _id: Some(ObjectId::new()),
+            ..meeting
+        };
+
+        db.meeting.insert_one(&meeting, None).await?;
+        Ok(meeting)
+    }
+
+    pub async fn get_meeting_by_id(
+        db: Arc<Database>,
+        meeting_id: ObjectId,
+    ) -> mongodb::error::Result<Option<Meeting>> {
+        let filter = doc! { "_id": meeting_id };
+        let meeting = db.meeting.find_one(filter, None).await?;
+
+        Ok(meeting)
+    }
+
+    pub async fn get_meeting_by_code(
+        db: Arc<Database>,
+        room_code: &str,
+    ) -> mongodb::error::Result<Option<Meeting>> {
+        // A series shares one code across occurrences, so pick the next one
+        // that has not ended.
+        let filter = doc! { "room_code": room_code, "ended": false };
+        let options = FindOneOptions::builder()
+            .sort(doc! { "start_time": 1 })
+            .build();
+        let meeting = db.meeting.find_one(filter, options).await?;
+
+        Ok(meeting)
+    }
+
+    /// Upcoming and running meetings the user hosts or is invited to, soonest
+    /// first.
+    pub async fn get_meetings_for_user(
         db: Arc<Database>,
-        room_code: String,
         user_id: ObjectId,
+        email: &str,
+    ) -> mongodb::error::Result<Vec<Meeting>> {
+        let filter = doc! {
+            "ended": false,
+            "$or": [
+                { "host_id": user_id },
+                { "invitees": email },
+            ]
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "start_time": 1 })
+            .build();
+
+        db.meeting.find(filter, options).await?.try_collect().await
+    }
+
+    pub async fn update_meeting(
+        db: Arc<Database>,
+        meeting: &Meeting,
     ) -> mongodb::error::Result<()> {
-        let new_participant = Participant {
+        let filter = doc! { "_id": meeting._id };
+        db.meeting.replace_one(filter, meeting, None).await?;
+        Ok(())
+    }
+
+    pub async fn delete_meeting(
+        db: Arc<Database>,
+        meeting_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "_id": meeting_id };
+        db.meeting.delete_one(filter, None).await?;
+        Ok(())
+    }
+
+    /// Running meetings due to end by `cutoff` whose participants have not
+    /// been warned yet.
+    pub async fn get_meetings_to_warn(
+        db: Arc<Database>,
+        cutoff: DateTime,
+    ) -> mongodb::error::Result<Vec<Meeting>> {
+        let filter = doc! {
+            "ended": false,
+            "warning_sent": false,
+            "end_time": { "$lte": cutoff },
+        };
+
+        db.meeting.find(filter, None).await?.try_collect().await
+    }
+
+    pub async fn get_meetings_to_end(
+        db: Arc<Database>,
+        now: DateTime,
+    ) -> mongodb::error::Result<Vec<Meeting>> {
+        let filter = doc! { "ended": false, "end_time": { "$lte": now } };
+
+        db.meeting.find(filter, None).await?.try_collect().await
+    }
+
+    pub async fn mark_meeting_warned(
+        db: Arc<Database>,
+        meeting_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "_id": meeting_id };
+        let update = doc! { "$set": { "warning_sent": true } };
+
+        db.meeting.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn mark_meeting_ended(
+        db: Arc<Database>,
+        meeting_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "_id": meeting_id };
+        let update = doc! { "$set": { "ended": true } };
+
+        db.meeting.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn create_series(
+        db: Arc<Database>,
+        series: MeetingSeries,
+    ) -> mongodb::error::Result<MeetingSeries> {
+        let series = MeetingSeries {
             _id: Some(ObjectId::new()),
-            user_id,
-            room_code,
+            ..series
         };
 
-        db.participant.insert_one(new_participant, None).await?;
+        db.series.insert_one(&series, None).await?;
+        Ok(series)
+    }
+
+    pub async fn get_series_by_id(
+        db: Arc<Database>,
+        series_id: ObjectId,
+    ) -> mongodb::error::Result<Option<MeetingSeries>> {
+        let filter = doc! { "_id": series_id };
+        let series = db.series.find_one(filter, None).await?;
+
+        Ok(series)
+    }
+
+    pub async fn update_series(
+        db: Arc<Database>,
+        series: &MeetingSeries,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "_id": series._id };
+        db.series.replace_one(filter, series, None).await?;
+        Ok(())
+    }
 
+    /// Deletes the series along with its occurrences that have not ended.
+    pub async fn delete_series(
+        db: Arc<Database>,
+        series_id: ObjectId,
+    ) -> mongodb::error::Result<()> {
+        db.meeting
+            .delete_many(doc! { "series_id": series_id, "ended": false }, None)
+            .await?;
+        db.series
+            .delete_one(doc! { "_id": series_id }, None)
+            .await?;
         Ok(())
     }
+
+    pub async fn add_series_exception(
+        db: Arc<Database>,
+        series_id: ObjectId,
+        start: DateTime,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "_id": series_id };
+        let update = doc! {
+            "$addToSet": { "recurrence.exceptions": start }
+        };
+
+        db.series.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    /// Every stored occurrence of a series, including ended ones, in start
+    /// order.
+    pub async fn get_series_occurrences(
+        db: Arc<Database>,
+        series_id: ObjectId,
+    ) -> mongodb::error::Result<Vec<Meeting>> {
+        let filter = doc! { "series_id": series_id };
+        let options = FindOptions::builder()
+            .sort(doc! { "start_time": 1 })
+            .build();
+
+        db.meeting.find(filter, options).await?.try_collect().await
+    }
+
+    /// Removes occurrences that start after `now` so they can be rebuilt from
+    /// an edited rule.
+    pub async fn delete_upcoming_occurrences(
+        db: Arc<Database>,
+        series_id: ObjectId,
+        now: DateTime,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! {
+            "series_id": series_id,
+            "ended": false,
+            "start_time": { "$gt": now },
+        };
+
+        db.meeting.delete_many(filter, None).await?;
+        Ok(())
+    }
+
+    /// Empties a room between occurrences while keeping its code, settings and
+    /// bans.
+    pub async fn reset_room(db: Arc<Database>, room_code: &str) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code };
+        let update = doc! {
+            "$set": { "participants_id": [], "lobby": [], "roles": [] }
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn set_calendar_token(
+        db: Arc<Database>,
+        user_id: ObjectId,
+        token: &str,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "_id": user_id };
+        let update = doc! { "$set": { "calendar_token": token } };
+
+        db.user.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    pub async fn get_user_by_calendar_token(
+        db: Arc<Database>,
+        token: &str,
+    ) -> mongodb::error::Result<Option<User>> {
+        let filter = doc! { "calendar_token": token };
+        let user = db.user.find_one(filter, None).await?;
+
+        Ok(user)
+    }
+
+    /// The series whose room uses `room_code`, if any.
+    pub async fn get_series_by_code(
+        db: Arc<Database>,
+        room_code: &str,
+    ) -> mongodb::error::Result<Option<MeetingSeries>> {
+        let filter = doc! { "room_code": room_code };
+        let series = db.series.find_one(filter, None).await?;
+
+        Ok(series)
+    }
+
+    pub async fn add_invite(
+        db: Arc<Database>,
+        room_code: &str,
+        invite: &InviteLink,
+    ) -> mongodb::error::Result<()> {
+        let filter = doc! { "code": room_code };
+        let update = doc! {
+            "$push": { "invites": to_bson(invite)? }
+        };
+
+        db.room.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    /// Revokes an invite, returning whether it existed.
+    pub async fn remove_invite(
+        db: Arc<Database>,
+        room_code: &str,
+        invite_id: ObjectId,
+    ) -> mongodb::error::Result<bool> {
+        let filter = doc! { "code": room_code, "invites.id": invite_id };
+        let update = doc! {
+            "$pull": { "invites": { "id": invite_id } }
+        };
+
+        let result = db.room.update_one(filter, update, None).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Counts one use of an invite. The update only applies while the use
+    /// count is still `seen_uses`, so two joins racing for the last use cannot
+    /// both succeed.
+    pub async fn use_invite(
+        db: Arc<Database>,
+        room_code: &str,
+        invite_id: ObjectId,
+        seen_uses: u32,
+    ) -> mongodb::error::Result<bool> {
+        let filter = doc! {
+            "code": room_code,
+            "invites": { "$elemMatch": { "id": invite_id, "uses": seen_uses } },
+        };
+        let update = doc! { "$inc": { "invites.$.uses": 1 } };
+
+        let result = db.room.update_one(filter, update, None).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn get_all_rooms(db: Arc<Database>) -> mongodb::error::Result<Vec<Room>> {
+        db.room.find(doc! {}, None).await?.try_collect().await
+    }
+
+    pub async fn archive_session(
+        db: Arc<Database>,
+        history: RoomHistory,
+    ) -> mongodb::error::Result<()> {
+        let history = RoomHistory {
+            _id: Some(ObjectId::new()),
+            ..history
+        };
+
+        db.history.insert_one(history, None).await?;
+        Ok(())
+    }
+
+    /// When the last archived session under `room_code` ended. Series reuse
+    /// their code, so this marks where the current session began.
+    pub async fn get_last_archived_end(
+        db: Arc<Database>,
+        room_code: &str,
+    ) -> mongodb::error::Result<Option<DateTime>> {
+        let filter = doc! { "room_code": room_code };
+        let options = FindOneOptions::builder()
+            .sort(doc! { "ended_at": -1 })
+            .build();
+        let history = db.history.find_one(filter, options).await?;
+
+        Ok(history.map(|history| history.ended_at))
+    }
+
+    /// The meeting under `room_code` that started most recently before `now`.
+    pub async fn get_latest_started_meeting(
+        db: Arc<Database>,
+        room_code: &str,
+        now: DateTime,
+    ) -> mongodb::error::Result<Option<Meeting>> {
+        let filter = doc! { "room_code": room_code, "start_time": { "$lte": now } };
+        let options = FindOneOptions::builder()
+            .sort(doc! { "start_time": -1 })
+            .build();
+        let meeting = db.meeting.find_one(filter, options).await?;
+
+        Ok(meeting)
+    }
+
+    /// Stores a chat message, stamping it with a new id and the current time.
+    /// Private messages are kept in their own collection, apart from the
+    /// room's transcript.
+    pub async fn insert_message(
+        db: Arc<Database>,
+        message: ChatMessage,
+    ) -> mongodb::error::Result<ChatMessage> {
+        let message = ChatMessage {
+            _id: Some(ObjectId::new()),
+            sent_at: DateTime::now(),
+            ..message
+        };
+
+        db.messages_of(&message).insert_one(&message, None).await?;
+        Ok(message)
+    }
+
+    /// The collection a message is kept in.
+    fn messages_of(&self, message: &ChatMessage) -> &Collection<ChatMessage> {
+        if message.is_private() {
+            &self.private_message
+        } else {
+            &self.message
+        }
+    }
+
+    /// The newest top-level messages in a room, newest first. `since` leaves out
+    /// messages from an earlier room that used the same code, and `before` pages
+    /// back from a message id.
+    pub async fn get_messages(
+        db: Arc<Database>,
+        room_code: &str,
+        since: Option<DateTime>,
+        before: Option<ObjectId>,
+        limit: i64,
+    ) -> mongodb::error::Result<Vec<ChatMessage>> {
+        let mut filter = doc! { "room_code": room_code, "reply_to": null };
+        if let Some(since) = since {
+            filter.insert("sent_at", doc! { "$gte": since });
+        }
+        if let Some(before) = before {
+            filter.insert("_id", doc! { "$lt": before });
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .build();
+
+        db.message.find(filter, options).await?.try_collect().await
+    }
+
+    /// The newest private messages `user_id` sent or received in a room,
+    /// newest first.
+    pub async fn get_private_messages(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+        since: Option<DateTime>,
+        before: Option<ObjectId>,
+        limit: i64,
+    ) -> mongodb::error::Result<Vec<ChatMessage>> {
+        let mut filter = doc! {
+            "room_code": room_code,
+            "$or": [ { "sender_id": user_id }, { "recipients": user_id } ]
+        };
+        if let Some(since) = since {
+            filter.insert("sent_at", doc! { "$gte": since });
+        }
+        if let Some(before) = before {
+            filter.insert("_id", doc! { "$lt": before });
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .build();
+
+        db.private_message
+            .find(filter, options)
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// The newest replies in a thread, newest first.
+    pub async fn get_thread(
+        db: Arc<Database>,
+        parent_id: ObjectId,
+        before: Option<ObjectId>,
+        limit: i64,
+    ) -> mongodb::error::Result<Vec<ChatMessage>> {
+        let mut filter = doc! { "reply_to": parent_id };
+        if let Some(before) = before {
+            filter.insert("_id", doc! { "$lt": before });
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .build();
+
+        db.message.find(filter, options).await?.try_collect().await
+    }
+
+    /// Counts a new reply on its thread's parent, returning the updated parent.
+    pub async fn record_reply(
+        db: Arc<Database>,
+        parent_id: ObjectId,
+        sent_at: DateTime,
+    ) -> mongodb::error::Result<Option<ChatMessage>> {
+        let filter = doc! { "_id": parent_id };
+        let update = doc! {
+            "$inc": { "reply_count": 1 },
+            "$max": { "last_reply_at": sent_at }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        db.message
+            .find_one_and_update(filter, update, options)
+            .await
+    }
+
+    /// Looks a message up in the room transcripts, then among private messages.
+    pub async fn get_message(
+        db: Arc<Database>,
+        message_id: ObjectId,
+    ) -> mongodb::error::Result<Option<ChatMessage>> {
+        let filter = doc! { "_id": message_id };
+        match db.message.find_one(filter.clone(), None).await? {
+            Some(message) => Ok(Some(message)),
+            None => db.private_message.find_one(filter, None).await,
+        }
+    }
+
+    /// Replaces a message's text, keeping the old one in `edits`. Returns the
+    /// updated message, or `None` if it changed or was deleted in the meantime.
+    pub async fn edit_message(
+        db: Arc<Database>,
+        current: &ChatMessage,
+        message: &str,
+    ) -> mongodb::error::Result<Option<ChatMessage>> {
+        let Some(message_id) = current._id else {
+            return Ok(None);
+        };
+        let now = DateTime::now();
+        let edit = to_bson(&MessageEdit {
+            message: current.message.clone(),
+            replaced_at: now,
+        })?;
+        let filter = doc! {
+            "_id": message_id,
+            "message": &current.message,
+            "deleted_at": null
+        };
+        let update = doc! {
+            "$set": { "message": message, "edited_at": now },
+            "$push": { "edits": edit }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        db.messages_of(current)
+            .find_one_and_update(filter, update, options)
+            .await
+    }
+
+    /// Turns a message into a tombstone. Returns whether it was still there to
+    /// delete.
+    pub async fn delete_message(
+        db: Arc<Database>,
+        current: &ChatMessage,
+        deleted_by: ObjectId,
+    ) -> mongodb::error::Result<bool> {
+        let Some(message_id) = current._id else {
+            return Ok(false);
+        };
+        let now = DateTime::now();
+        let edit = to_bson(&MessageEdit {
+            message: current.message.clone(),
+            replaced_at: now,
+        })?;
+        let filter = doc! {
+            "_id": message_id,
+            "message": &current.message,
+            "deleted_at": null
+        };
+        let update = doc! {
+            "$set": { "message": "", "deleted_at": now, "deleted_by": deleted_by },
+            "$push": { "edits": edit }
+        };
+
+        let result = db
+            .messages_of(current)
+            .update_one(filter, update, None)
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    /// Adds or removes one user's reaction, dropping emoji nobody uses any
+    /// more. Returns the updated message unless it is gone or deleted.
+    pub async fn set_reaction(
+        db: Arc<Database>,
+        current: &ChatMessage,
+        emoji: &str,
+        user_id: ObjectId,
+        reacted: bool,
+    ) -> mongodb::error::Result<Option<ChatMessage>> {
+        let Some(message_id) = current._id else {
+            return Ok(None);
+        };
+        let messages = db.messages_of(current);
+        let live = doc! { "_id": message_id, "deleted_at": null };
+
+        if reacted {
+            let mut filter = live.clone();
+            filter.insert("reactions.emoji", emoji);
+            let update = doc! { "$addToSet": { "reactions.$.user_ids": user_id } };
+            let result = messages.update_one(filter, update, None).await?;
+
+            if result.matched_count == 0 {
+                let mut filter = live.clone();
+                filter.insert("reactions.emoji", doc! { "$ne": emoji });
+                let update = doc! {
+                    "$push": { "reactions": { "emoji": emoji, "user_ids": [user_id] } }
+                };
+                messages.update_one(filter, update, None).await?;
+            }
+        } else {
+            let mut filter = live.clone();
+            filter.insert("reactions.emoji", emoji);
+            let update = doc! { "$pull": { "reactions.$.user_ids": user_id } };
+            messages.update_one(filter, update, None).await?;
+
+            let update = doc! { "$pull": { "reactions": { "user_ids": { "$size": 0 } } } };
+            messages.update_one(live.clone(), update, None).await?;
+        }
+
+        messages.find_one(live, None).await
+    }
+
+    /// Moves a user's read marker forward to `message_id`. Private messages
+    /// have markers of their own, so reading one says nothing about the
+    /// transcript. Returns `false` when the marker already pointed at that
+    /// message or a later one.
+    pub async fn mark_read(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+        message_id: ObjectId,
+        private: bool,
+    ) -> mongodb::error::Result<bool> {
+        let filter = doc! {
+            "room_code": room_code,
+            "user_id": user_id,
+            "message_id": { "$lt": message_id }
+        };
+        let update = doc! {
+            "$set": { "message_id": message_id, "read_at": DateTime::now() }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        // A marker that is already ahead fails the filter, and the upsert then
+        // collides with it on the unique index.
+        let markers = if private {
+            &db.private_read_marker
+        } else {
+            &db.read_marker
+        };
+        match markers.update_one(filter, update, options).await {
+            Ok(_) => Ok(true),
+            Err(err) if Database::is_duplicate_key(&err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub async fn get_read_markers(
+        db: Arc<Database>,
+        room_code: &str,
+        since: Option<DateTime>,
+    ) -> mongodb::error::Result<Vec<ReadMarker>> {
+        let mut filter = doc! { "room_code": room_code };
+        if let Some(since) = since {
+            filter.insert("read_at", doc! { "$gte": since });
+        }
+
+        db.read_marker.find(filter, None).await?.try_collect().await
+    }
+
+    /// The private read markers of `user_ids` in a room.
+    pub async fn get_private_read_markers(
+        db: Arc<Database>,
+        room_code: &str,
+        since: Option<DateTime>,
+        user_ids: &[ObjectId],
+    ) -> mongodb::error::Result<Vec<ReadMarker>> {
+        let mut filter = doc! { "room_code": room_code, "user_id": { "$in": user_ids } };
+        if let Some(since) = since {
+            filter.insert("read_at", doc! { "$gte": since });
+        }
+
+        db.private_read_marker
+            .find(filter, None)
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// Messages in the transcript from others that `user_id` has not read.
+    /// Thread replies are left out, as they are from `get_messages`.
+    pub async fn count_unread(
+        db: Arc<Database>,
+        room_code: &str,
+        user_id: ObjectId,
+        since: Option<DateTime>,
+        read_up_to: Option<ObjectId>,
+    ) -> mongodb::error::Result<u64> {
+        let mut filter = doc! {
+            "room_code": room_code,
+            "sender_id": { "$ne": user_id },
+            "reply_to": null,
+            "deleted_at": null
+        };
+        if let Some(since) = since {
+            filter.insert("sent_at", doc! { "$gte": since });
+        }
+        if let Some(read_up_to) = read_up_to {
+            filter.insert("_id", doc! { "$gt": read_up_to });
+        }
+
+        db.message.count_documents(filter, None).await
+    }
 }
@@ -1,6 +1,7 @@
 mod api;
 mod db;
 mod models;
+mod room_state;
 mod utils;
 mod ws;
 
@@ -22,8 +23,10 @@ use std::collections::HashMap;
 use tokio::sync::Mutex;
 
 use crate::{
-    api::{auth::auth_router, room::room_router},
+    api::{auth::auth_router, meeting::meeting_router, room::room_router},
     db::connection::Database,
+    models::plan_model::PlanCatalog,
+    utils::room_code::{RoomCodeGenerator, generator_from_env},
     ws::AppState,
 };
 
@@ -31,6 +34,7 @@ use crate::{
 pub struct SharedState {
     pub db: Arc<Database>,
     pub ws_state: Arc<AppState>,
+    pub code_generator: Arc<dyn RoomCodeGenerator>,
 }
 
 #[tokio::main]
@@ -45,20 +49,33 @@ async fn main() {
     // let (tx, _rx) = broadcast::channel(100);
     let user_sockets = Arc::new(Mutex::new(HashMap::new()));
     let sockets = Arc::new(Mutex::new(HashMap::new()));
+    let guests = Arc::new(Mutex::new(HashMap::new()));
+    let room_states = Arc::new(Mutex::new(HashMap::new()));
 
     let app_state = Arc::new(AppState {
         user_sockets,
         sockets,
+        guests,
+        room_states,
+        plans: PlanCatalog::from_env(),
     });
 
+    tokio::spawn(ws::expire_lobby_requests(db.clone(), app_state.clone()));
+    tokio::spawn(ws::end_scheduled_meetings(db.clone(), app_state.clone()));
+    tokio::spawn(ws::sweep_idle_rooms(db.clone(), app_state.clone()));
+    tokio::spawn(ws::enforce_duration_limits(db.clone(), app_state.clone()));
+    tokio::spawn(ws::end_timed_breakouts(db.clone(), app_state.clone()));
+    tokio::spawn(ws::expire_typing(db.clone(), app_state.clone()));
+
     let shared_state = SharedState {
         db: db.clone(),
         ws_state: app_state,
+        code_generator: Arc::from(generator_from_env()),
     };
 
     let cors = CorsLayer::new()
         .allow_origin(HeaderValue::from_static("http://localhost:5173"))
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE, Method::OPTIONS])
         .allow_headers([CONTENT_TYPE, AUTHORIZATION])
         .allow_credentials(true)
         .max_age(Duration::from_secs(3600));
@@ -66,6 +83,7 @@ async fn main() {
     let app = Router::new()
         .nest("/auth", auth_router())
         .nest("/room", room_router())
+        .nest("/meeting", meeting_router())
         .route("/ws", get(ws::handler))
         .layer(CookieManagerLayer::new())
         .layer(cors)
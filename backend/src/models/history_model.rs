@@ -0,0 +1,33 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Serialize, Deserialize};
+
+use crate::models::participant_model::AttendanceSummary;
+
+/// What is kept of a room session after it ends and its code is freed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RoomHistory {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")] 
+    pub _id: Option<ObjectId>,
+
+    pub room_code: String,
+    pub host_id: ObjectId,
+
+    // Set when the session was a scheduled meeting or one of a series.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meeting_id: Option<ObjectId>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<ObjectId>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    pub started_at: DateTime,
+    pub ended_at: DateTime,
+    pub duration_secs: i64,
+    pub end_reason: String,
+    pub peak_participants: u32,
+
+    #[serde(default)]
+    pub attendance: Vec<AttendanceSummary>,
+}
@@ -0,0 +1,361 @@
+use chrono::{Datelike, Days, FixedOffset, Months};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Serialize, Deserialize};
+use std::env;
+
+/// A meeting booked ahead of time. Its room is created when the meeting is
+/// scheduled so the code can be shared with invitees straight away.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Meeting {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")] 
+    pub _id: Option<ObjectId>,
+
+    pub room_code: String,
+    pub host_id: ObjectId,
+    pub title: String,
+
+    #[serde(default)]
+    pub description: String,
+
+    pub start_time: DateTime,
+    pub end_time: DateTime,
+
+    // IANA name such as `Europe/Berlin`, kept so clients can show the time
+    // the host scheduled in. `start_time` and `end_time` are always UTC.
+    pub timezone: String,
+
+    // Invitee email addresses.
+    #[serde(default)]
+    pub invitees: Vec<String>,
+
+    // Set on occurrences of a recurring series, together with the start the
+    // recurrence rule gave them before any single-occurrence edit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<ObjectId>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurrence_start: Option<DateTime>,
+
+    #[serde(default)]
+    pub warning_sent: bool,
+
+    #[serde(default)]
+    pub ended: bool,
+
+    pub created_at: DateTime,
+}
+
+impl Meeting {
+    /// When joining opens: `MEETING_JOIN_WINDOW_MINS` (10 by default) before
+    /// the scheduled start.
+    pub fn join_opens_at(&self) -> DateTime {
+        let window_mins: i64 = env::var("MEETING_JOIN_WINDOW_MINS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10);
+        DateTime::from_millis(self.start_time.timestamp_millis() - window_mins * 60 * 1000)
+    }
+
+    pub fn is_invited(&self, email: &str) -> bool {
+        self.invitees.iter().any(|invitee| invitee.eq_ignore_ascii_case(email))
+    }
+}
+
+/// A recurring meeting. Every occurrence shares the series' room code and is
+/// stored as its own `Meeting` so it can be edited or cancelled alone.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MeetingSeries {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")] 
+    pub _id: Option<ObjectId>,
+
+    pub room_code: String,
+    pub host_id: ObjectId,
+    pub title: String,
+
+    #[serde(default)]
+    pub description: String,
+
+    // Start of the first occurrence; later ones keep its time of day.
+    pub start_time: DateTime,
+    pub duration_mins: i64,
+
+    pub timezone: String,
+
+    // Offset of the first start, used to decide which local day an
+    // occurrence falls on.
+    #[serde(default)]
+    pub utc_offset_mins: i32,
+
+    #[serde(default)]
+    pub invitees: Vec<String>,
+
+    pub recurrence: Recurrence,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    pub fn days_from_monday(self) -> u64 {
+        self as u64
+    }
+
+    fn from_chrono(weekday: chrono::Weekday) -> Self {
+        match weekday {
+            chrono::Weekday::Mon => Weekday::Mon,
+            chrono::Weekday::Tue => Weekday::Tue,
+            chrono::Weekday::Wed => Weekday::Wed,
+            chrono::Weekday::Thu => Weekday::Thu,
+            chrono::Weekday::Fri => Weekday::Fri,
+            chrono::Weekday::Sat => Weekday::Sat,
+            chrono::Weekday::Sun => Weekday::Sun,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+
+    // Repeat every `interval` days, weeks or months.
+    #[serde(default = "default_interval")]
+    pub interval: u32,
+
+    // Weekly series only; defaults to the weekday of the first start.
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+
+    // Scheduled starts that were cancelled.
+    #[serde(default)]
+    pub exceptions: Vec<DateTime>,
+}
+
+fn default_interval() -> u32 {
+    1
+}
+
+impl Recurrence {
+    /// Start times the rule produces from `first_start` up to `horizon`, plus
+    /// the first one after it so a running series always has a next
+    /// occurrence. Exceptions are left out. Monthly series that start on a
+    /// day some months lack fall back to the last day of those months.
+    pub fn starts(&self, first_start: DateTime, utc_offset_mins: i32, horizon: DateTime) -> Vec<DateTime> {
+        let mut starts = Vec::new();
+        let (Some(offset), Some(first)) = (
+            FixedOffset::east_opt(utc_offset_mins * 60),
+            chrono::DateTime::from_timestamp_millis(first_start.timestamp_millis()),
+        ) else {
+            return starts;
+        };
+        let first = first.with_timezone(&offset);
+        let interval = self.interval.max(1);
+
+        let mut weekdays = self.weekdays.clone();
+        if weekdays.is_empty() {
+            weekdays.push(Weekday::from_chrono(first.weekday()));
+        }
+        weekdays.sort();
+        weekdays.dedup();
+
+        let mut generated = 0;
+        for period in 0.. {
+            let step = period * interval;
+            let candidates = match self.frequency {
+                Frequency::Daily => vec![first.checked_add_days(Days::new(step as u64))],
+                Frequency::Weekly => {
+                    let monday = first.checked_sub_days(Days::new(
+                        first.weekday().num_days_from_monday() as u64,
+                    ));
+                    weekdays
+                        .iter()
+                        .map(|weekday| {
+                            monday?.checked_add_days(Days::new(step as u64 * 7 + weekday.days_from_monday()))
+                        })
+                        .collect()
+                }
+                Frequency::Monthly => vec![first.checked_add_months(Months::new(step))],
+            };
+
+            for candidate in candidates {
+                let Some(candidate) = candidate else {
+                    return starts;
+                };
+                if candidate < first {
+                    continue;
+                }
+
+                let start = DateTime::from_millis(candidate.timestamp_millis());
+                if self.until.is_some_and(|until| start > until)
+                    || self.count.is_some_and(|count| generated >= count)
+                {
+                    return starts;
+                }
+
+                generated += 1;
+                if self.exceptions.contains(&start) {
+                    continue;
+                }
+                starts.push(start);
+                if start > horizon {
+                    return starts;
+                }
+            }
+        }
+
+        starts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime {
+        let time = chrono::DateTime::parse_from_rfc3339(time).unwrap();
+        DateTime::from_millis(time.timestamp_millis())
+    }
+
+    fn rule(frequency: Frequency) -> Recurrence {
+        Recurrence {
+            frequency,
+            interval: 1,
+            weekdays: vec![],
+            until: None,
+            count: None,
+            exceptions: vec![],
+        }
+    }
+
+    fn far_future() -> DateTime {
+        at("2100-01-01T00:00:00Z")
+    }
+
+    #[test]
+    fn daily_series_stops_after_count() {
+        let recurrence = Recurrence { count: Some(3), ..rule(Frequency::Daily) };
+        let starts = recurrence.starts(at("2026-01-05T10:00:00Z"), 0, far_future());
+
+        assert_eq!(starts, vec![
+            at("2026-01-05T10:00:00Z"),
+            at("2026-01-06T10:00:00Z"),
+            at("2026-01-07T10:00:00Z"),
+        ]);
+    }
+
+    #[test]
+    fn weekly_series_visits_each_weekday_from_the_first_start() {
+        // The first start is a Wednesday, so that week's Monday is skipped.
+        let recurrence = Recurrence {
+            weekdays: vec![Weekday::Fri, Weekday::Mon, Weekday::Wed],
+            count: Some(4),
+            ..rule(Frequency::Weekly)
+        };
+        let starts = recurrence.starts(at("2026-01-07T09:00:00Z"), 0, far_future());
+
+        assert_eq!(starts, vec![
+            at("2026-01-07T09:00:00Z"),
+            at("2026-01-09T09:00:00Z"),
+            at("2026-01-12T09:00:00Z"),
+            at("2026-01-14T09:00:00Z"),
+        ]);
+    }
+
+    #[test]
+    fn weekly_series_defaults_to_the_first_weekday_and_honours_interval() {
+        let recurrence = Recurrence { interval: 2, count: Some(3), ..rule(Frequency::Weekly) };
+        let starts = recurrence.starts(at("2026-01-07T09:00:00Z"), 0, far_future());
+
+        assert_eq!(starts, vec![
+            at("2026-01-07T09:00:00Z"),
+            at("2026-01-21T09:00:00Z"),
+            at("2026-02-04T09:00:00Z"),
+        ]);
+    }
+
+    #[test]
+    fn weekdays_are_taken_in_the_hosts_timezone() {
+        // 23:30 UTC on Monday is already Tuesday at UTC+1.
+        let recurrence = Recurrence {
+            weekdays: vec![Weekday::Tue, Weekday::Thu],
+            count: Some(2),
+            ..rule(Frequency::Weekly)
+        };
+        let starts = recurrence.starts(at("2026-01-05T23:30:00Z"), 60, far_future());
+
+        assert_eq!(starts, vec![at("2026-01-05T23:30:00Z"), at("2026-01-07T23:30:00Z")]);
+    }
+
+    #[test]
+    fn monthly_series_clamps_to_the_end_of_shorter_months() {
+        let recurrence = Recurrence { count: Some(4), ..rule(Frequency::Monthly) };
+        let starts = recurrence.starts(at("2026-01-31T15:00:00Z"), 0, far_future());
+
+        assert_eq!(starts, vec![
+            at("2026-01-31T15:00:00Z"),
+            at("2026-02-28T15:00:00Z"),
+            at("2026-03-31T15:00:00Z"),
+            at("2026-04-30T15:00:00Z"),
+        ]);
+    }
+
+    #[test]
+    fn until_is_inclusive() {
+        let recurrence = Recurrence {
+            until: Some(at("2026-01-07T10:00:00Z")),
+            ..rule(Frequency::Daily)
+        };
+        let starts = recurrence.starts(at("2026-01-05T10:00:00Z"), 0, far_future());
+
+        assert_eq!(starts.len(), 3);
+        assert_eq!(starts.last(), Some(&at("2026-01-07T10:00:00Z")));
+    }
+
+    #[test]
+    fn exceptions_are_skipped_but_still_count() {
+        let recurrence = Recurrence {
+            count: Some(3),
+            exceptions: vec![at("2026-01-06T10:00:00Z")],
+            ..rule(Frequency::Daily)
+        };
+        let starts = recurrence.starts(at("2026-01-05T10:00:00Z"), 0, far_future());
+
+        assert_eq!(starts, vec![at("2026-01-05T10:00:00Z"), at("2026-01-07T10:00:00Z")]);
+    }
+
+    #[test]
+    fn open_ended_series_stop_one_past_the_horizon() {
+        let recurrence = rule(Frequency::Daily);
+        let starts = recurrence.starts(at("2026-01-05T10:00:00Z"), 0, at("2026-01-07T12:00:00Z"));
+
+        assert_eq!(starts, vec![
+            at("2026-01-05T10:00:00Z"),
+            at("2026-01-06T10:00:00Z"),
+            at("2026-01-07T10:00:00Z"),
+            at("2026-01-08T10:00:00Z"),
+        ]);
+    }
+}
@@ -0,0 +1,89 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Serialize, Deserialize};
+
+/// A chat message as stored for the room's transcript. The id and `sent_at`
+/// are assigned by the server. Deleting leaves a tombstone with an empty
+/// `message`; earlier texts stay in `edits` for moderation.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub _id: Option<ObjectId>,
+
+    pub room_code: String,
+    pub sender_id: ObjectId,
+    pub username: String,
+    pub message: String,
+    pub sent_at: DateTime,
+
+    // Set on private messages, which only the sender and these users see.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<ObjectId>,
+
+    // Set on thread replies: the top-level message they answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<ObjectId>,
+
+    #[serde(default)]
+    pub reply_count: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_reply_at: Option<DateTime>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<DateTime>,
+
+    // Previous texts, oldest first, including the text of a deleted message.
+    #[serde(default)]
+    pub edits: Vec<MessageEdit>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<ObjectId>,
+
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+}
+
+impl ChatMessage {
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    pub fn is_private(&self) -> bool {
+        !self.recipients.is_empty()
+    }
+
+    /// Whether `user_id` sent or received this message. Everyone in the room
+    /// is part of a message to the whole room.
+    pub fn involves(&self, user_id: &ObjectId) -> bool {
+        !self.is_private() || self.sender_id == *user_id || self.recipients.contains(user_id)
+    }
+}
+
+/// A text a message had before it was edited or deleted.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MessageEdit {
+    pub message: String,
+    pub replaced_at: DateTime,
+}
+
+/// Everyone who reacted to a message with one emoji.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Reaction {
+    pub emoji: String,
+    pub user_ids: Vec<ObjectId>,
+}
+
+/// The newest message of a room's transcript a user has read.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ReadMarker {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub _id: Option<ObjectId>,
+
+    pub room_code: String,
+    pub user_id: ObjectId,
+    pub message_id: ObjectId,
+    pub read_at: DateTime,
+}
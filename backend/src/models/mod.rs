@@ -1,3 +1,7 @@
 pub mod user_model;
 pub mod room_model;
-pub mod participant_model;
\ No newline at end of file
+pub mod participant_model;
+pub mod meeting_model;
+pub mod history_model;
+pub mod plan_model;
+pub mod message_model;
\ No newline at end of file
@@ -1,6 +1,8 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Serialize, Deserialize};
 
+/// One attendance session: a user's time in a room from joining until they
+/// leave, disconnect or the room ends. `left_at` is unset while it is open.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Participant {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")] 
@@ -8,4 +10,223 @@ pub struct Participant {
 
     pub user_id: ObjectId,
     pub room_code: String,
-}
\ No newline at end of file
+
+    #[serde(default)]
+    pub username: String,
+
+    #[serde(default)]
+    pub guest: bool,
+
+    // Whether the user hosted the room during this session.
+    #[serde(default)]
+    pub host: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub joined_at: Option<DateTime>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_at: Option<DateTime>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leave_reason: Option<String>,
+}
+
+/// One user's attendance in a room, added up over their sessions.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AttendanceSummary {
+    pub user_id: ObjectId,
+    pub username: String,
+    pub guest: bool,
+    pub sessions: u32,
+    pub first_joined_at: DateTime,
+
+    // Unset while the user is still in the room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_left_at: Option<DateTime>,
+
+    pub total_duration_secs: i64,
+}
+
+/// Adds up `records` per user in first-join order. Sessions still open count
+/// until now, and everything is clipped to `from`..`to` when given.
+pub fn summarize_attendance(
+    records: &[Participant],
+    from: Option<DateTime>,
+    to: Option<DateTime>,
+) -> Vec<AttendanceSummary> {
+    let now = DateTime::now().timestamp_millis();
+    let mut summaries: Vec<AttendanceSummary> = Vec::new();
+    let mut still_present: Vec<ObjectId> = Vec::new();
+
+    for record in records {
+        let Some(joined_at) = record.joined_at else {
+            continue;
+        };
+        let start = joined_at.timestamp_millis().max(from.map_or(i64::MIN, |from| from.timestamp_millis()));
+        let end = record
+            .left_at
+            .map_or(now, |left_at| left_at.timestamp_millis())
+            .min(to.map_or(i64::MAX, |to| to.timestamp_millis()));
+        let secs = (end - start).max(0) / 1000;
+
+        if record.left_at.is_none() {
+            still_present.push(record.user_id);
+        }
+
+        match summaries.iter_mut().find(|summary| summary.user_id == record.user_id) {
+            Some(summary) => {
+                summary.sessions += 1;
+                summary.total_duration_secs += secs;
+                summary.last_left_at = summary.last_left_at.max(record.left_at);
+            }
+            None => summaries.push(AttendanceSummary {
+                user_id: record.user_id,
+                username: record.username.clone(),
+                guest: record.guest,
+                sessions: 1,
+                first_joined_at: joined_at,
+                last_left_at: record.left_at,
+                total_duration_secs: secs,
+            }),
+        }
+    }
+
+    for summary in summaries.iter_mut() {
+        if still_present.contains(&summary.user_id) {
+            summary.last_left_at = None;
+        }
+    }
+
+    summaries
+}
+
+/// The most sessions that were open at the same time.
+pub fn peak_attendance(records: &[Participant]) -> usize {
+    let now = DateTime::now();
+    let mut events: Vec<(DateTime, i32)> = Vec::new();
+    for record in records {
+        if let Some(joined_at) = record.joined_at {
+            events.push((joined_at, 1));
+            events.push((record.left_at.unwrap_or(now), -1));
+        }
+    }
+    // Leaves sort before joins at the same instant.
+    events.sort();
+
+    let mut current = 0;
+    let mut peak = 0;
+    for (_, change) in events {
+        current += change;
+        peak = peak.max(current);
+    }
+    peak as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mins(minutes: i64) -> DateTime {
+        DateTime::from_millis(1_767_600_000_000 + minutes * 60 * 1000)
+    }
+
+    fn session(user_id: ObjectId, joined: i64, left: Option<i64>) -> Participant {
+        Participant {
+            _id: None,
+            user_id,
+            room_code: "123456".to_string(),
+            username: "ada".to_string(),
+            guest: false,
+            host: false,
+            joined_at: Some(mins(joined)),
+            left_at: left.map(mins),
+            device: None,
+            leave_reason: None,
+        }
+    }
+
+    #[test]
+    fn sessions_are_added_up_per_user_in_first_join_order() {
+        let ada = ObjectId::new();
+        let bob = ObjectId::new();
+        let records = vec![
+            session(ada, 0, Some(10)),
+            session(bob, 5, Some(20)),
+            session(ada, 30, Some(45)),
+        ];
+
+        let summaries = summarize_attendance(&records, None, None);
+
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].user_id, ada);
+        assert_eq!(summaries[0].sessions, 2);
+        assert_eq!(summaries[0].first_joined_at, mins(0));
+        assert_eq!(summaries[0].last_left_at, Some(mins(45)));
+        assert_eq!(summaries[0].total_duration_secs, 25 * 60);
+        assert_eq!(summaries[1].user_id, bob);
+        assert_eq!(summaries[1].total_duration_secs, 15 * 60);
+    }
+
+    #[test]
+    fn open_sessions_leave_the_user_present() {
+        let ada = ObjectId::new();
+        let records = vec![session(ada, 0, Some(10)), session(ada, 20, None)];
+
+        let summaries = summarize_attendance(&records, None, Some(mins(50)));
+
+        assert_eq!(summaries[0].last_left_at, None);
+        assert_eq!(summaries[0].total_duration_secs, 40 * 60);
+    }
+
+    #[test]
+    fn sessions_are_clipped_to_the_range() {
+        let ada = ObjectId::new();
+        let records = vec![session(ada, 0, Some(60))];
+
+        let summaries = summarize_attendance(&records, Some(mins(15)), Some(mins(45)));
+
+        assert_eq!(summaries[0].total_duration_secs, 30 * 60);
+    }
+
+    #[test]
+    fn sessions_outside_the_range_count_as_zero() {
+        let ada = ObjectId::new();
+        let records = vec![session(ada, 0, Some(10))];
+
+        let summaries = summarize_attendance(&records, Some(mins(20)), None);
+
+        assert_eq!(summaries[0].total_duration_secs, 0);
+    }
+
+    #[test]
+    fn records_without_a_join_time_are_skipped() {
+        let mut record = session(ObjectId::new(), 0, Some(10));
+        record.joined_at = None;
+
+        assert!(summarize_attendance(&[record], None, None).is_empty());
+    }
+
+    #[test]
+    fn peak_counts_overlapping_sessions() {
+        let records = vec![
+            session(ObjectId::new(), 0, Some(30)),
+            session(ObjectId::new(), 10, Some(20)),
+            session(ObjectId::new(), 15, Some(40)),
+        ];
+
+        assert_eq!(peak_attendance(&records), 3);
+    }
+
+    #[test]
+    fn peak_does_not_count_a_handover_as_overlap() {
+        let records = vec![
+            session(ObjectId::new(), 0, Some(10)),
+            session(ObjectId::new(), 10, Some(20)),
+        ];
+
+        assert_eq!(peak_attendance(&records), 1);
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Serialize, Deserialize};
+use std::{env, fs};
+
+use crate::models::user_model::User;
+
+/// Limits applied to the rooms a user hosts. A missing cap means unlimited.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Plan {
+    pub name: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_meeting_mins: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_rooms: Option<u32>,
+
+    // Counts the host, like `RoomSettings::max_participants`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_participants: Option<u32>,
+
+    #[serde(default)]
+    pub remote_control: bool,
+}
+
+impl Plan {
+    fn free() -> Self {
+        Plan {
+            name: "free".to_string(),
+            max_meeting_mins: Some(40),
+            max_concurrent_rooms: Some(1),
+            max_participants: Some(100),
+            remote_control: false,
+        }
+    }
+
+    fn pro() -> Self {
+        Plan {
+            name: "pro".to_string(),
+            max_meeting_mins: None,
+            max_concurrent_rooms: Some(10),
+            max_participants: Some(300),
+            remote_control: true,
+        }
+    }
+}
+
+/// The configured plans. Users without a known plan get the default one.
+#[derive(Debug, Clone)]
+pub struct PlanCatalog {
+    plans: Vec<Plan>,
+    default_plan: usize,
+}
+
+impl PlanCatalog {
+    /// Reads the plans from the JSON array in `PLANS_FILE`, falling back to the
+    /// built-in `free` and `pro` plans. `DEFAULT_PLAN` names the plan given to
+    /// users without one (`free` by default).
+    pub fn from_env() -> Self {
+        let plans = env::var("PLANS_FILE")
+            .ok()
+            .and_then(|path| match fs::read_to_string(&path) {
+                Ok(contents) => Some((path, contents)),
+                Err(err) => {
+                    eprintln!("Failed to read plans from {}: {}", path, err);
+                    None
+                }
+            })
+            .and_then(|(path, contents)| match serde_json::from_str::<Vec<Plan>>(&contents) {
+                Ok(plans) if !plans.is_empty() => Some(plans),
+                Ok(_) => None,
+                Err(err) => {
+                    eprintln!("Failed to parse plans in {}: {}", path, err);
+                    None
+                }
+            })
+            .unwrap_or_else(|| vec![Plan::free(), Plan::pro()]);
+
+        let default_name = env::var("DEFAULT_PLAN").unwrap_or_else(|_| "free".to_string());
+        let default_plan = plans.iter().position(|plan| plan.name == default_name).unwrap_or(0);
+
+        PlanCatalog { plans, default_plan }
+    }
+
+    pub fn plan_for(&self, user: &User) -> &Plan {
+        user.plan.as_deref()
+            .and_then(|name| self.plans.iter().find(|plan| plan.name == name))
+            .unwrap_or(self.default_plan())
+    }
+
+    pub fn default_plan(&self) -> &Plan {
+        &self.plans[self.default_plan]
+    }
+}
@@ -1,4 +1,4 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -11,4 +11,298 @@ pub struct Room {
 
     #[serde(default)]
     pub participants_id: Vec<ObjectId>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime>,
+
+    #[serde(default)]
+    pub settings: RoomSettings,
+
+    #[serde(default)]
+    pub lobby: Vec<LobbyEntry>,
+
+    // Members without an entry are attendees; the host is always `host_id`.
+    #[serde(default)]
+    pub roles: Vec<RoleAssignment>,
+
+    #[serde(default)]
+    pub banned: Vec<BanEntry>,
+
+    #[serde(default)]
+    pub invites: Vec<InviteLink>,
+
+    // Set on breakout rooms, which are created and emptied from their parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_code: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakout_name: Option<String>,
+
+    // When a parent room's breakouts are closed automatically.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakouts_end_at: Option<DateTime>,
+}
+
+impl Room {
+    pub fn role_of(&self, user_id: &ObjectId) -> Role {
+        if *user_id == self.host_id {
+            return Role::Host;
+        }
+
+        self.roles
+            .iter()
+            .find(|assignment| assignment.user_id == *user_id)
+            .map(|assignment| assignment.role)
+            .unwrap_or(Role::Attendee)
+    }
+
+    pub fn can(&self, user_id: &ObjectId, permission: Permission) -> bool {
+        let is_member = *user_id == self.host_id || self.participants_id.contains(user_id);
+        is_member && self.role_of(user_id).can(permission)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    Host,
+    CoHost,
+    Presenter,
+    Attendee,
+    Viewer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Admit,
+    Kick,
+    GrantRemoteControl,
+    ShareScreen,
+    Chat,
+    AssignRoles,
+    Mute,
+    ManageHands,
+}
+
+impl Role {
+    pub fn can(self, permission: Permission) -> bool {
+        use Permission::*;
+
+        match self {
+            Role::Host => true,
+            Role::CoHost => !matches!(permission, AssignRoles),
+            Role::Presenter => matches!(permission, ShareScreen | Chat),
+            Role::Attendee => matches!(permission, Chat),
+            Role::Viewer => false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BanEntry {
+    pub user_id: ObjectId,
+    pub banned_by: ObjectId,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    pub banned_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RoleAssignment {
+    pub user_id: ObjectId,
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LobbyEntry {
+    pub user_id: ObjectId,
+    pub username: String,
+
+    #[serde(default)]
+    pub guest: bool,
+
+    pub requested_at: DateTime,
+
+    // Role from the invite link the user joined with, applied on admission.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+/// A shareable join link. The signed token only names the invite, so the
+/// entry here is what gets revoked and counts uses.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InviteLink {
+    pub id: ObjectId,
+    pub created_by: ObjectId,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_uses: Option<u32>,
+
+    #[serde(default)]
+    pub uses: u32,
+
+    // Lets the holder skip the lobby.
+    #[serde(default)]
+    pub pre_approved: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+}
+
+impl InviteLink {
+    pub fn is_exhausted(&self) -> bool {
+        self.max_uses.is_some_and(|max_uses| self.uses >= max_uses)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RoomSettings {
+    #[serde(default)]
+    pub guest_access: bool,
+
+    #[serde(default)]
+    pub locked: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_participants: Option<u32>,
+
+    // bcrypt hash of the join password, never sent to clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+
+    #[serde(default)]
+    pub auto_admit_same_domain: bool,
+
+    #[serde(default)]
+    pub auto_admit_users: Vec<ObjectId>,
+
+    #[serde(default = "default_true")]
+    pub chat_enabled: bool,
+
+    #[serde(default = "default_true")]
+    pub private_chat_enabled: bool,
+
+    #[serde(default = "default_true")]
+    pub remote_control_allowed: bool,
+
+    #[serde(default)]
+    pub join_muted: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            guest_access: false,
+            locked: false,
+            max_participants: None,
+            password_hash: None,
+            auto_admit_same_domain: false,
+            auto_admit_users: vec![],
+            chat_enabled: true,
+            private_chat_enabled: true,
+            remote_control_allowed: true,
+            join_muted: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Permission; 8] = [
+        Permission::Admit,
+        Permission::Kick,
+        Permission::GrantRemoteControl,
+        Permission::ShareScreen,
+        Permission::Chat,
+        Permission::AssignRoles,
+        Permission::Mute,
+        Permission::ManageHands,
+    ];
+
+    fn granted(role: Role) -> Vec<Permission> {
+        ALL.into_iter().filter(|permission| role.can(*permission)).collect()
+    }
+
+    #[test]
+    fn host_can_do_everything() {
+        assert_eq!(granted(Role::Host), ALL.to_vec());
+    }
+
+    #[test]
+    fn co_host_can_do_everything_but_assign_roles() {
+        let expected: Vec<Permission> = ALL
+            .into_iter()
+            .filter(|permission| *permission != Permission::AssignRoles)
+            .collect();
+        assert_eq!(granted(Role::CoHost), expected);
+    }
+
+    #[test]
+    fn presenter_can_share_and_chat() {
+        assert_eq!(granted(Role::Presenter), vec![Permission::ShareScreen, Permission::Chat]);
+    }
+
+    #[test]
+    fn attendee_can_only_chat() {
+        assert_eq!(granted(Role::Attendee), vec![Permission::Chat]);
+    }
+
+    #[test]
+    fn viewer_can_do_nothing() {
+        assert!(granted(Role::Viewer).is_empty());
+    }
+
+    fn room() -> Room {
+        Room {
+            _id: None,
+            host_id: ObjectId::new(),
+            code: "123456".to_string(),
+            participants_id: vec![],
+            created_at: None,
+            settings: RoomSettings::default(),
+            lobby: vec![],
+            roles: vec![],
+            banned: vec![],
+            invites: vec![],
+            parent_code: None,
+            breakout_name: None,
+            breakouts_end_at: None,
+        }
+    }
+
+    #[test]
+    fn members_without_an_assignment_are_attendees() {
+        let mut room = room();
+        let attendee = ObjectId::new();
+        let presenter = ObjectId::new();
+        room.participants_id = vec![attendee, presenter];
+        room.roles.push(RoleAssignment { user_id: presenter, role: Role::Presenter });
+
+        assert_eq!(room.role_of(&room.host_id), Role::Host);
+        assert_eq!(room.role_of(&attendee), Role::Attendee);
+        assert_eq!(room.role_of(&presenter), Role::Presenter);
+        assert!(room.can(&presenter, Permission::ShareScreen));
+        assert!(!room.can(&attendee, Permission::ShareScreen));
+    }
+
+    #[test]
+    fn non_members_have_no_permissions() {
+        let mut room = room();
+        let outsider = ObjectId::new();
+        room.roles.push(RoleAssignment { user_id: outsider, role: Role::CoHost });
+
+        assert!(ALL.into_iter().all(|permission| !room.can(&outsider, permission)));
+    }
 }
@@ -10,6 +10,14 @@ pub struct User {
     pub email: String,
     pub password: String,
 
+    // Secret in the user's calendar feed URL, created on first request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calendar_token: Option<String>,
+
+    // Name of the plan setting the user's hosting limits; unset means the default plan.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<String>,
+
     // #[serde(skip_serializing_if = "Option::is_none")] 
     // pub refresh_token: Option<String>,
 }
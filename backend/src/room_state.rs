@@ -0,0 +1,353 @@
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// What the server knows about one member's media and connection, built from
+/// the events they send rather than from another client's UI.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct MemberState {
+    pub user_id: ObjectId,
+    pub username: String,
+    pub audio: bool,
+    pub video: bool,
+    pub screen: bool,
+    pub hand_raised: bool,
+    pub connected: bool,
+    // Set when a host or co-host muted this member; they must ask before
+    // unmuting again.
+    pub muted_by_host: bool,
+}
+
+impl MemberState {
+    pub fn new(user_id: ObjectId, username: &str) -> Self {
+        MemberState {
+            user_id,
+            username: username.to_string(),
+            audio: false,
+            video: false,
+            screen: false,
+            hand_raised: false,
+            connected: true,
+            muted_by_host: false,
+        }
+    }
+
+    /// The fields that differ from `previous`, keyed by their serialized names.
+    pub fn diff(&self, previous: &MemberState) -> Map<String, Value> {
+        let (Ok(Value::Object(current)), Ok(Value::Object(previous))) =
+            (serde_json::to_value(self), serde_json::to_value(previous))
+        else {
+            return Map::new();
+        };
+
+        current
+            .into_iter()
+            .filter(|(key, value)| previous.get(key) != Some(value))
+            .collect()
+    }
+
+    fn full(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Map::new(),
+        }
+    }
+}
+
+/// Live state of everyone in one room, kept in memory next to the sockets.
+#[derive(Debug, Default, Clone)]
+pub struct RoomState {
+    pub members: Vec<MemberState>,
+    // Members with a raised hand, longest waiting first.
+    pub hand_queue: Vec<ObjectId>,
+    // When each member recently sent a meeting reaction, oldest first.
+    pub recent_reactions: HashMap<ObjectId, VecDeque<Instant>>,
+    pub typing: HashMap<ObjectId, Typing>,
+}
+
+/// A member who is typing: when they last said so, and when the others were
+/// last told.
+#[derive(Debug, Clone, Copy)]
+pub struct Typing {
+    pub last_seen: Instant,
+    pub last_sent: Instant,
+}
+
+impl RoomState {
+    pub fn member(&self, user_id: &ObjectId) -> Option<&MemberState> {
+        self.members.iter().find(|member| member.user_id == *user_id)
+    }
+
+    /// Marks a member as connected, adding them if they are new. Returns the
+    /// changed fields, or every field for a new member.
+    pub fn join(&mut self, user_id: ObjectId, username: &str) -> Map<String, Value> {
+        match self.members.iter().position(|member| member.user_id == user_id) {
+            Some(_) => self
+                .update(&user_id, |member| {
+                    member.username = username.to_string();
+                    member.connected = true;
+                })
+                .unwrap_or_default(),
+            None => {
+                let member = MemberState::new(user_id, username);
+                let fields = member.full();
+                self.members.push(member);
+                fields
+            }
+        }
+    }
+
+    /// Applies `change` to a member, returning the changed fields if anything
+    /// actually changed.
+    pub fn update(
+        &mut self,
+        user_id: &ObjectId,
+        change: impl FnOnce(&mut MemberState),
+    ) -> Option<Map<String, Value>> {
+        let member = self.members.iter_mut().find(|member| member.user_id == *user_id)?;
+        let previous = member.clone();
+        change(member);
+
+        let changes = member.diff(&previous);
+        (!changes.is_empty()).then_some(changes)
+    }
+
+    /// Raises a member's hand, queueing them behind hands already up.
+    pub fn raise_hand(&mut self, user_id: &ObjectId) -> Option<Map<String, Value>> {
+        self.member(user_id)?;
+        if !self.hand_queue.contains(user_id) {
+            self.hand_queue.push(*user_id);
+        }
+        self.update(user_id, |member| member.hand_raised = true)
+    }
+
+    pub fn lower_hand(&mut self, user_id: &ObjectId) -> Option<Map<String, Value>> {
+        self.hand_queue.retain(|id| id != user_id);
+        self.update(user_id, |member| member.hand_raised = false)
+    }
+
+    /// Records a meeting reaction unless the member already sent `limit` of
+    /// them within `window`.
+    pub fn allow_reaction(&mut self, user_id: ObjectId, limit: usize, window: Duration) -> bool {
+        let now = Instant::now();
+        let sent = self.recent_reactions.entry(user_id).or_default();
+        while sent.front().is_some_and(|at| now.duration_since(*at) >= window) {
+            sent.pop_front();
+        }
+
+        if sent.len() >= limit {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+
+    /// Marks a member as typing. Returns whether the others should be told,
+    /// which happens at most once per `throttle` while they keep typing.
+    pub fn start_typing(&mut self, user_id: ObjectId, throttle: Duration) -> bool {
+        let now = Instant::now();
+        match self.typing.get_mut(&user_id) {
+            Some(typing) => {
+                typing.last_seen = now;
+                if now.duration_since(typing.last_sent) < throttle {
+                    return false;
+                }
+                typing.last_sent = now;
+                true
+            }
+            None => {
+                let typing = Typing {
+                    last_seen: now,
+                    last_sent: now,
+                };
+                self.typing.insert(user_id, typing);
+                true
+            }
+        }
+    }
+
+    /// Returns whether the member was typing.
+    pub fn stop_typing(&mut self, user_id: &ObjectId) -> bool {
+        self.typing.remove(user_id).is_some()
+    }
+
+    /// Stops and returns everyone who has not said they are typing for
+    /// `timeout`.
+    pub fn expire_typing(&mut self, timeout: Duration) -> Vec<ObjectId> {
+        let now = Instant::now();
+        let expired: Vec<ObjectId> = self
+            .typing
+            .iter()
+            .filter(|(_, typing)| now.duration_since(typing.last_seen) >= timeout)
+            .map(|(user_id, _)| *user_id)
+            .collect();
+        for user_id in &expired {
+            self.typing.remove(user_id);
+        }
+        expired
+    }
+
+    pub fn remove(&mut self, user_id: &ObjectId) {
+        self.members.retain(|member| member.user_id != *user_id);
+        self.hand_queue.retain(|id| id != user_id);
+        self.recent_reactions.remove(user_id);
+        self.typing.remove(user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room_with(count: usize) -> (RoomState, Vec<ObjectId>) {
+        let mut state = RoomState::default();
+        let ids: Vec<ObjectId> = (0..count).map(|_| ObjectId::new()).collect();
+        for id in &ids {
+            state.join(*id, "member");
+        }
+        (state, ids)
+    }
+
+    #[test]
+    fn hands_queue_in_the_order_they_were_raised() {
+        let (mut state, ids) = room_with(3);
+        state.raise_hand(&ids[2]);
+        state.raise_hand(&ids[0]);
+        state.raise_hand(&ids[1]);
+
+        assert_eq!(state.hand_queue, vec![ids[2], ids[0], ids[1]]);
+        assert!(state.member(&ids[0]).unwrap().hand_raised);
+    }
+
+    #[test]
+    fn raising_again_keeps_the_place_in_the_queue() {
+        let (mut state, ids) = room_with(2);
+        state.raise_hand(&ids[0]);
+        state.raise_hand(&ids[1]);
+
+        assert_eq!(state.raise_hand(&ids[0]), None);
+        assert_eq!(state.hand_queue, vec![ids[0], ids[1]]);
+    }
+
+    #[test]
+    fn lowering_leaves_the_queue() {
+        let (mut state, ids) = room_with(3);
+        for id in &ids {
+            state.raise_hand(id);
+        }
+
+        let changes = state.lower_hand(&ids[1]).unwrap();
+        assert_eq!(changes.get("hand_raised"), Some(&Value::Bool(false)));
+        assert_eq!(state.hand_queue, vec![ids[0], ids[2]]);
+        assert_eq!(state.lower_hand(&ids[1]), None);
+    }
+
+    #[test]
+    fn only_members_can_raise_a_hand() {
+        let (mut state, _) = room_with(1);
+        let outsider = ObjectId::new();
+
+        assert_eq!(state.raise_hand(&outsider), None);
+        assert!(state.hand_queue.is_empty());
+    }
+
+    #[test]
+    fn removing_a_member_drops_their_hand() {
+        let (mut state, ids) = room_with(2);
+        state.raise_hand(&ids[0]);
+        state.raise_hand(&ids[1]);
+        state.remove(&ids[0]);
+
+        assert_eq!(state.hand_queue, vec![ids[1]]);
+        assert!(state.member(&ids[0]).is_none());
+    }
+
+    #[test]
+    fn reactions_are_limited_within_the_window() {
+        let mut state = RoomState::default();
+        let user_id = ObjectId::new();
+        let window = Duration::from_secs(60);
+
+        assert!(state.allow_reaction(user_id, 2, window));
+        assert!(state.allow_reaction(user_id, 2, window));
+        assert!(!state.allow_reaction(user_id, 2, window));
+        assert!(state.allow_reaction(ObjectId::new(), 2, window));
+    }
+
+    #[test]
+    fn reactions_older_than_the_window_stop_counting() {
+        let mut state = RoomState::default();
+        let user_id = ObjectId::new();
+        let window = Duration::from_secs(5);
+        let old = Instant::now().checked_sub(Duration::from_secs(10)).unwrap();
+        state.recent_reactions.insert(user_id, VecDeque::from([old, old]));
+
+        assert!(state.allow_reaction(user_id, 2, window));
+        assert_eq!(state.recent_reactions[&user_id].len(), 1);
+    }
+
+    #[test]
+    fn rejected_reactions_are_not_recorded() {
+        let mut state = RoomState::default();
+        let user_id = ObjectId::new();
+        let window = Duration::from_secs(60);
+
+        assert!(state.allow_reaction(user_id, 1, window));
+        assert!(!state.allow_reaction(user_id, 1, window));
+        assert_eq!(state.recent_reactions[&user_id].len(), 1);
+    }
+
+    fn ago(secs: u64) -> Instant {
+        Instant::now().checked_sub(Duration::from_secs(secs)).unwrap()
+    }
+
+    #[test]
+    fn typing_is_announced_once_per_throttle() {
+        let mut state = RoomState::default();
+        let user_id = ObjectId::new();
+        let throttle = Duration::from_secs(3);
+
+        assert!(state.start_typing(user_id, throttle));
+        assert!(!state.start_typing(user_id, throttle));
+
+        state.typing.get_mut(&user_id).unwrap().last_sent = ago(5);
+        assert!(state.start_typing(user_id, throttle));
+    }
+
+    #[test]
+    fn throttled_typing_still_counts_as_seen() {
+        let mut state = RoomState::default();
+        let user_id = ObjectId::new();
+        state.typing.insert(user_id, Typing { last_seen: ago(10), last_sent: ago(1) });
+
+        assert!(!state.start_typing(user_id, Duration::from_secs(3)));
+        assert!(state.expire_typing(Duration::from_secs(5)).is_empty());
+    }
+
+    #[test]
+    fn stopping_reports_whether_they_were_typing() {
+        let mut state = RoomState::default();
+        let user_id = ObjectId::new();
+        state.start_typing(user_id, Duration::from_secs(3));
+
+        assert!(state.stop_typing(&user_id));
+        assert!(!state.stop_typing(&user_id));
+        assert!(state.start_typing(user_id, Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn quiet_typists_expire() {
+        let mut state = RoomState::default();
+        let (quiet, active) = (ObjectId::new(), ObjectId::new());
+        state.typing.insert(quiet, Typing { last_seen: ago(10), last_sent: ago(10) });
+        state.start_typing(active, Duration::from_secs(3));
+
+        assert_eq!(state.expire_typing(Duration::from_secs(5)), vec![quiet]);
+        assert!(!state.typing.contains_key(&quiet));
+        assert!(state.typing.contains_key(&active));
+    }
+}
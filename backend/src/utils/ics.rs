@@ -0,0 +1,304 @@
+use chrono::{FixedOffset, NaiveDate};
+use mongodb::bson::DateTime;
+
+use crate::models::meeting_model::{Frequency, Recurrence};
+
+const PRODUCT_ID: &str = "-//backend//meetings//EN";
+const UID_DOMAIN: &str = "meetings";
+const BYDAY_CODES: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+/// One VEVENT. Times are written in UTC.
+pub struct CalendarEvent {
+    pub uid: String,
+    pub title: String,
+    pub description: String,
+    pub start: DateTime,
+    pub end: DateTime,
+    pub join_url: String,
+    pub organizer_name: String,
+    pub organizer_email: String,
+    pub attendees: Vec<String>,
+    pub rrule: Option<String>,
+    pub exdates: Vec<DateTime>,
+}
+
+/// Wraps events in a VCALENDAR with CRLF line endings and folded lines.
+pub fn calendar(name: &str, events: &[CalendarEvent]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+    ];
+
+    let stamp = format_time(DateTime::now());
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@{}", event.uid, UID_DOMAIN));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART:{}", format_time(event.start)));
+        lines.push(format!("DTEND:{}", format_time(event.end)));
+        if let Some(rrule) = &event.rrule {
+            lines.push(format!("RRULE:{}", rrule));
+        }
+        if !event.exdates.is_empty() {
+            let exdates: Vec<String> = event.exdates.iter().map(|time| format_time(*time)).collect();
+            lines.push(format!("EXDATE:{}", exdates.join(",")));
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&event.title)));
+        if !event.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape_text(&event.description)));
+        }
+        lines.push(format!("LOCATION:{}", escape_text(&event.join_url)));
+        lines.push(format!("URL:{}", event.join_url));
+        lines.push(format!(
+            "ORGANIZER;CN={}:mailto:{}",
+            quote_param(&event.organizer_name),
+            event.organizer_email
+        ));
+        for attendee in &event.attendees {
+            lines.push(format!("ATTENDEE;ROLE=REQ-PARTICIPANT;RSVP=TRUE:mailto:{}", attendee));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line)).collect::<String>()
+}
+
+/// Builds the RRULE for a series whose first occurrence starts at
+/// `first_start`. Weekdays are chosen in the series' own offset, so they are
+/// shifted when that day differs from the UTC day of DTSTART.
+pub fn rrule(recurrence: &Recurrence, first_start: DateTime, utc_offset_mins: i32) -> String {
+    let mut parts = vec![format!(
+        "FREQ={}",
+        match recurrence.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+        }
+    )];
+    if recurrence.interval > 1 {
+        parts.push(format!("INTERVAL={}", recurrence.interval));
+    }
+
+    if recurrence.frequency == Frequency::Weekly && !recurrence.weekdays.is_empty() {
+        let shift = utc_day_shift(first_start, utc_offset_mins);
+        let days: Vec<&str> = recurrence
+            .weekdays
+            .iter()
+            .map(|weekday| BYDAY_CODES[(weekday.days_from_monday() as i64 + shift).rem_euclid(7) as usize])
+            .collect();
+        parts.push(format!("BYDAY={}", days.join(",")));
+    }
+
+    // Months without the start day fall back to their last day.
+    if recurrence.frequency == Frequency::Monthly
+        && let Some(start) = chrono::DateTime::from_timestamp_millis(first_start.timestamp_millis())
+        && chrono::Datelike::day(&start) > 28
+    {
+        parts.push(format!("BYMONTHDAY={},-1;BYSETPOS=1", chrono::Datelike::day(&start)));
+    }
+
+    if let Some(until) = recurrence.until {
+        parts.push(format!("UNTIL={}", format_time(until)));
+    }
+    if let Some(count) = recurrence.count {
+        parts.push(format!("COUNT={}", count));
+    }
+
+    parts.join(";")
+}
+
+/// Days between the local date of `time` in the given offset and its UTC date.
+fn utc_day_shift(time: DateTime, utc_offset_mins: i32) -> i64 {
+    let (Some(utc), Some(offset)) = (
+        chrono::DateTime::from_timestamp_millis(time.timestamp_millis()),
+        FixedOffset::east_opt(utc_offset_mins * 60),
+    ) else {
+        return 0;
+    };
+    let utc_date: NaiveDate = utc.date_naive();
+    let local_date: NaiveDate = utc.with_timezone(&offset).date_naive();
+    (utc_date - local_date).num_days()
+}
+
+fn format_time(time: DateTime) -> String {
+    chrono::DateTime::from_timestamp_millis(time.timestamp_millis())
+        .map(|time| time.format("%Y%m%dT%H%M%SZ").to_string())
+        .unwrap_or_default()
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn quote_param(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "'"))
+}
+
+/// Splits a content line into 75-octet pieces joined by CRLF and a space,
+/// without breaking UTF-8 sequences.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::meeting_model::Weekday;
+
+    fn at(time: &str) -> DateTime {
+        let time = chrono::DateTime::parse_from_rfc3339(time).unwrap();
+        DateTime::from_millis(time.timestamp_millis())
+    }
+
+    fn rule(frequency: Frequency) -> Recurrence {
+        Recurrence {
+            frequency,
+            interval: 1,
+            weekdays: vec![],
+            until: None,
+            count: None,
+            exceptions: vec![],
+        }
+    }
+
+    fn event() -> CalendarEvent {
+        CalendarEvent {
+            uid: "abc".to_string(),
+            title: "Standup; daily, short".to_string(),
+            description: "Line one\nLine two".to_string(),
+            start: at("2026-01-05T10:00:00Z"),
+            end: at("2026-01-05T10:15:00Z"),
+            join_url: "https://example.com/join/123456".to_string(),
+            organizer_name: "Ada \"The\" Host".to_string(),
+            organizer_email: "ada@example.com".to_string(),
+            attendees: vec!["bob@example.com".to_string()],
+            rrule: Some("FREQ=DAILY;COUNT=3".to_string()),
+            exdates: vec![at("2026-01-06T10:00:00Z")],
+        }
+    }
+
+    #[test]
+    fn text_values_are_escaped() {
+        assert_eq!(escape_text("a;b,c\\d\r\ne\nf"), r"a\;b\,c\\d\ne\nf");
+    }
+
+    #[test]
+    fn parameters_are_quoted_without_inner_quotes() {
+        assert_eq!(quote_param("Ada \"The\" Host"), "\"Ada 'The' Host\"");
+    }
+
+    #[test]
+    fn long_lines_are_folded_at_75_octets() {
+        let line = "X".repeat(200);
+        let folded = fold(&line);
+
+        assert!(folded.ends_with("\r\n"));
+        for piece in folded.trim_end_matches("\r\n").split("\r\n") {
+            assert!(piece.len() <= 75, "{}", piece.len());
+        }
+        assert_eq!(folded.trim_end_matches("\r\n").replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn folding_never_splits_a_character() {
+        let line = "é".repeat(100);
+        let folded = fold(&line);
+
+        for piece in folded.trim_end_matches("\r\n").split("\r\n") {
+            assert!(piece.len() <= 75);
+        }
+        assert_eq!(folded.trim_end_matches("\r\n").replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn weekly_rrule_lists_interval_days_and_count() {
+        let recurrence = Recurrence {
+            interval: 2,
+            weekdays: vec![Weekday::Mon, Weekday::Wed],
+            count: Some(5),
+            ..rule(Frequency::Weekly)
+        };
+
+        assert_eq!(
+            rrule(&recurrence, at("2026-01-05T10:00:00Z"), 0),
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;COUNT=5"
+        );
+    }
+
+    #[test]
+    fn weekly_rrule_shifts_days_to_the_utc_date_of_dtstart() {
+        // Tuesday 00:30 at UTC+1 is Monday 23:30 UTC.
+        let recurrence = Recurrence {
+            weekdays: vec![Weekday::Tue, Weekday::Thu],
+            ..rule(Frequency::Weekly)
+        };
+
+        assert_eq!(
+            rrule(&recurrence, at("2026-01-05T23:30:00Z"), 60),
+            "FREQ=WEEKLY;BYDAY=MO,WE"
+        );
+    }
+
+    #[test]
+    fn monthly_rrule_falls_back_to_the_last_day() {
+        assert_eq!(
+            rrule(&rule(Frequency::Monthly), at("2026-01-31T10:00:00Z"), 0),
+            "FREQ=MONTHLY;BYMONTHDAY=31,-1;BYSETPOS=1"
+        );
+        assert_eq!(rrule(&rule(Frequency::Monthly), at("2026-01-15T10:00:00Z"), 0), "FREQ=MONTHLY");
+    }
+
+    #[test]
+    fn rrule_until_is_written_in_utc() {
+        let recurrence = Recurrence {
+            until: Some(at("2026-03-01T00:00:00+02:00")),
+            ..rule(Frequency::Daily)
+        };
+
+        assert_eq!(
+            rrule(&recurrence, at("2026-01-05T10:00:00Z"), 0),
+            "FREQ=DAILY;UNTIL=20260228T220000Z"
+        );
+    }
+
+    #[test]
+    fn calendar_wraps_escaped_events() {
+        let ics = calendar("Team, calendar", &[event()]);
+        let lines: Vec<&str> = ics.trim_end_matches("\r\n").split("\r\n").collect();
+
+        assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+        assert_eq!(lines.last(), Some(&"END:VCALENDAR"));
+        assert!(ics.ends_with("\r\n"));
+        assert!(lines.contains(&r"X-WR-CALNAME:Team\, calendar"));
+        assert!(lines.contains(&"UID:abc@meetings"));
+        assert!(lines.contains(&"DTSTART:20260105T100000Z"));
+        assert!(lines.contains(&"DTEND:20260105T101500Z"));
+        assert!(lines.contains(&"RRULE:FREQ=DAILY;COUNT=3"));
+        assert!(lines.contains(&"EXDATE:20260106T100000Z"));
+        assert!(lines.contains(&r"SUMMARY:Standup\; daily\, short"));
+        assert!(lines.contains(&r"DESCRIPTION:Line one\nLine two"));
+        assert!(lines.contains(&"ORGANIZER;CN=\"Ada 'The' Host\":mailto:ada@example.com"));
+        assert!(lines.contains(&"ATTENDEE;ROLE=REQ-PARTICIPANT;RSVP=TRUE:mailto:bob@example.com"));
+    }
+}
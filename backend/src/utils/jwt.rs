@@ -62,6 +62,7 @@ pub fn generate_refresh_token(user_id: &str) -> String {
     ).expect("Failed to generate refresh token.")
 }
 
+#[allow(dead_code)]
 pub fn verify_refresh_token(token: &str) -> Option<RefreshClaims> {
     let secret = env::var("REFRESH_TOKEN_SECRET").expect("Refresh token secret not found in .env");
     decode::<RefreshClaims>(
@@ -72,3 +73,88 @@ pub fn verify_refresh_token(token: &str) -> Option<RefreshClaims> {
     .ok()
     .map(|data| data.claims)
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GuestClaims {
+    pub sub: String,
+    pub username: String,
+    pub room: String,
+    pub guest: bool,
+    pub exp: usize,
+}
+
+pub fn generate_guest_token(guest_id: &str, username: &str, room_code: &str) -> String {
+    let expiration = Utc::now() + Duration::minutes(30);
+    let guest_claims = GuestClaims {
+        sub: guest_id.to_owned(),
+        username: username.to_owned(),
+        room: room_code.to_owned(),
+        guest: true,
+        exp: expiration.timestamp() as usize,
+    };
+    let secret = env::var("ACCESS_TOKEN_SECRET").expect("Access token secret not found in .env");
+
+    encode(
+        &Header::default(),
+        &guest_claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    ).expect("Failed to generate guest token.")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteClaims {
+    pub sub: String,
+    pub room: String,
+    pub invite: bool,
+    pub exp: usize,
+}
+
+pub fn generate_invite_token(invite_id: &str, room_code: &str, expires_at: i64) -> String {
+    let invite_claims = InviteClaims {
+        sub: invite_id.to_owned(),
+        room: room_code.to_owned(),
+        invite: true,
+        exp: expires_at as usize,
+    };
+    let secret = env::var("ACCESS_TOKEN_SECRET").expect("Access token secret not found in .env");
+
+    encode(
+        &Header::default(),
+        &invite_claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    ).expect("Failed to generate invite token.")
+}
+
+pub fn verify_invite_token(token: &str) -> Result<InviteClaims, Error> {
+    let secret = env::var("ACCESS_TOKEN_SECRET")
+        .expect("❌ Access token secret not found in .env");
+
+    let token_data = decode::<InviteClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &Validation::default(),
+    )?;
+
+    if !token_data.claims.invite {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
+
+    Ok(token_data.claims)
+}
+
+pub fn verify_guest_token(token: &str) -> Result<GuestClaims, Error> {
+    let secret = env::var("ACCESS_TOKEN_SECRET")
+        .expect("❌ Access token secret not found in .env");
+
+    let token_data = decode::<GuestClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &Validation::default(),
+    )?;
+
+    if !token_data.claims.guest {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
+
+    Ok(token_data.claims)
+}
@@ -1,2 +1,4 @@
 pub mod bcrypt;
-pub mod jwt;
\ No newline at end of file
+pub mod ics;
+pub mod jwt;
+pub mod room_code;
\ No newline at end of file
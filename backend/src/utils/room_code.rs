@@ -0,0 +1,120 @@
+use rand::{distributions::Alphanumeric, seq::SliceRandom, Rng};
+use std::env;
+
+const ADJECTIVES: &[&str] = &[
+    "amber", "bold", "brave", "calm", "clever", "coral", "crisp", "dusty", "eager", "fancy",
+    "gentle", "golden", "happy", "hidden", "icy", "jolly", "keen", "lively", "lucky", "mellow",
+    "misty", "noble", "olive", "proud", "quiet", "rapid", "rosy", "rustic", "silver", "sunny",
+    "swift", "tidy", "vivid", "warm", "wild", "witty",
+];
+
+const ANIMALS: &[&str] = &[
+    "badger", "beaver", "bison", "crane", "dingo", "eagle", "falcon", "ferret", "gecko", "heron",
+    "ibis", "jackal", "koala", "lemur", "lynx", "marten", "moose", "newt", "ocelot", "otter",
+    "panda", "puffin", "quail", "raven", "robin", "salmon", "seal", "stoat", "tapir", "toucan",
+    "walrus", "wombat", "yak", "zebra",
+];
+
+const SLUG_LENGTH: usize = 16;
+
+/// Produces candidate room codes. Uniqueness is enforced by the index on
+/// `rooms.code`, so implementations only need to make collisions unlikely.
+pub trait RoomCodeGenerator: Send + Sync {
+    fn generate(&self) -> String;
+}
+
+/// Six digit codes such as `482913`.
+pub struct NumericCode;
+
+impl RoomCodeGenerator for NumericCode {
+    fn generate(&self) -> String {
+        let code: u32 = rand::thread_rng().gen_range(100000..=999999);
+        code.to_string()
+    }
+}
+
+/// Readable codes such as `amber-otter-42`.
+pub struct WordCode;
+
+impl RoomCodeGenerator for WordCode {
+    fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+        let adjective = ADJECTIVES.choose(&mut rng).unwrap();
+        let animal = ANIMALS.choose(&mut rng).unwrap();
+        let number: u8 = rng.gen_range(10..=99);
+        format!("{}-{}-{}", adjective, animal, number)
+    }
+}
+
+/// Long lowercase alphanumeric slugs that are impractical to guess.
+pub struct SlugCode;
+
+impl RoomCodeGenerator for SlugCode {
+    fn generate(&self) -> String {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SLUG_LENGTH)
+            .map(|c| (c as char).to_ascii_lowercase())
+            .collect()
+    }
+}
+
+/// Picks the generator named by `ROOM_CODE_STYLE` (`numeric`, `words` or
+/// `slug`), defaulting to numeric codes.
+pub fn generator_from_env() -> Box<dyn RoomCodeGenerator> {
+    match env::var("ROOM_CODE_STYLE").unwrap_or_default().as_str() {
+        "words" => Box::new(WordCode),
+        "slug" => Box::new(SlugCode),
+        "" | "numeric" => Box::new(NumericCode),
+        other => {
+            eprintln!("Unknown ROOM_CODE_STYLE '{}', falling back to numeric codes", other);
+            Box::new(NumericCode)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_codes_are_six_digits() {
+        for _ in 0..100 {
+            let code = NumericCode.generate();
+            assert_eq!(code.len(), 6);
+            assert!(code.chars().all(|c| c.is_ascii_digit()));
+            assert!(!code.starts_with('0'));
+        }
+    }
+
+    #[test]
+    fn word_codes_are_adjective_animal_number() {
+        for _ in 0..100 {
+            let code = WordCode.generate();
+            let parts: Vec<&str> = code.split('-').collect();
+            assert_eq!(parts.len(), 3, "{}", code);
+            assert!(ADJECTIVES.contains(&parts[0]));
+            assert!(ANIMALS.contains(&parts[1]));
+            let number: u8 = parts[2].parse().unwrap();
+            assert!((10..=99).contains(&number));
+        }
+    }
+
+    #[test]
+    fn slug_codes_are_lowercase_alphanumeric() {
+        for _ in 0..100 {
+            let code = SlugCode.generate();
+            assert_eq!(code.len(), SLUG_LENGTH);
+            assert!(code.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn codes_never_need_url_escaping() {
+        let generators: [&dyn RoomCodeGenerator; 3] = [&NumericCode, &WordCode, &SlugCode];
+        for generator in generators {
+            let code = generator.generate();
+            assert!(code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'), "{}", code);
+        }
+    }
+}
@@ -1,10 +1,11 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, env, sync::Arc, time::Duration};
 
 use axum::{
     extract::{
         State,
         ws::{Message, WebSocket, WebSocketUpgrade},
     },
+    http::{HeaderMap, header::USER_AGENT},
     response::Response,
 };
 use futures_util::SinkExt as FuturesSinkExt;
@@ -12,7 +13,8 @@ use futures_util::{
     StreamExt,
     stream::{SplitSink, SplitStream},
 };
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, oid::ObjectId};
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tokio::task;
@@ -20,21 +22,63 @@ use uuid::Uuid;
 
 use crate::{
     SharedState,
+    api::meeting::schedule_occurrences,
     db::connection::Database,
-    models::{room_model::Room, user_model::User},
-    utils::jwt::{AccessClaims, verify_access_token},
+    models::{
+        history_model::RoomHistory,
+        meeting_model::Meeting,
+        message_model::{ChatMessage, Reaction, ReadMarker},
+        participant_model::{
+            Participant as AttendanceRecord, peak_attendance, summarize_attendance,
+        },
+        plan_model::{Plan, PlanCatalog},
+        room_model::{BanEntry, LobbyEntry, Permission, Role, Room},
+        user_model::User,
+    },
+    room_state::{MemberState, RoomState},
+    utils::{
+        bcrypt::verify_password,
+        jwt::{GuestClaims, verify_access_token, verify_guest_token, verify_invite_token},
+    },
 };
 
+pub type SocketSender = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+
+const MAX_BREAKOUTS: usize = 50;
+const MAX_EMOJI_LEN: usize = 32;
+// Meeting-wide reactions are limited to this set and to `REACTION_LIMIT` per
+// member every `REACTION_WINDOW`.
+const MEETING_REACTIONS: &[&str] = &["👏", "👍", "❤️", "😂", "😮", "🎉"];
+const REACTION_LIMIT: usize = 5;
+const REACTION_WINDOW: Duration = Duration::from_secs(5);
+// `typing` is repeated at most every `TYPING_THROTTLE` while someone keeps
+// typing, and stopped for them after `TYPING_TIMEOUT` of silence.
+const TYPING_THROTTLE: Duration = Duration::from_secs(3);
+const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
 #[derive(Clone)]
 pub struct AppState {
     pub user_sockets: Arc<Mutex<HashMap<ObjectId, Uuid>>>,
-    pub sockets: Arc<Mutex<HashMap<Uuid, Arc<Mutex<SplitSink<WebSocket, Message>>>>>>,
+    pub sockets: Arc<Mutex<HashMap<Uuid, SocketSender>>>,
+    pub guests: Arc<Mutex<HashMap<ObjectId, String>>>,
+    pub room_states: Arc<Mutex<HashMap<String, RoomState>>>,
+    pub plans: PlanCatalog,
 }
 
+// `code` may be left out when an invite token names the room.
 #[derive(Deserialize)]
 struct JoinRoomData {
     access_token: String,
-    code: String,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    invite_token: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    // Free-form client label such as "desktop"; the socket's User-Agent is
+    // recorded when it is missing.
+    #[serde(default)]
+    device: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -42,6 +86,32 @@ struct JoinRoomResponse {
     message_type: String,
     user_id: ObjectId,
     username: String,
+    guest: bool,
+}
+
+#[derive(Serialize)]
+struct JoinDenied {
+    message_type: String,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct AdmitFailed {
+    message_type: String,
+    user_id: ObjectId,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct FeatureDisabled {
+    message_type: String,
+    feature: String,
+}
+
+#[derive(Serialize)]
+struct GuestRestricted {
+    message_type: String,
+    action: String,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -61,11 +131,8 @@ struct Host {
 
 #[derive(Deserialize, Serialize)]
 struct RequestAcceptedData {
-    username: String,
     user_id: ObjectId,
-    participants: Vec<Participant>,
     code: String,
-    host: Host,
 }
 
 #[derive(Serialize)]
@@ -96,6 +163,32 @@ struct RequestRejectData {
     user_id: ObjectId,
 }
 
+#[derive(Deserialize)]
+struct LobbyActionData {
+    code: String,
+}
+
+#[derive(Serialize)]
+struct LobbyUpdated {
+    message_type: String,
+    code: String,
+    lobby: Vec<LobbyEntry>,
+}
+
+#[derive(Serialize)]
+struct LobbyPosition {
+    message_type: String,
+    code: String,
+    position: usize,
+    total: usize,
+}
+
+#[derive(Serialize)]
+struct LobbyExpired {
+    message_type: String,
+    code: String,
+}
+
 #[derive(Serialize)]
 struct RequestRejectResponse {
     message_type: String,
@@ -105,7 +198,7 @@ struct RequestRejectResponse {
 struct RtcConnectionData {
     item: serde_json::Value,
     to: ObjectId,
-    user_id: ObjectId,
+    code: String,
 }
 
 #[derive(Serialize)]
@@ -121,6 +214,7 @@ struct MouseMoveData {
     x: f64,
     y: f64,
     to: ObjectId,
+    code: String,
 }
 
 #[derive(Serialize)]
@@ -134,6 +228,7 @@ struct MouseMoveResponse {
 struct KeyPressData {
     key: String,
     to: ObjectId,
+    code: String,
 }
 
 #[derive(Serialize)]
@@ -145,6 +240,7 @@ struct KeyPressResponse {
 #[derive(Deserialize)]
 struct MouseClickData {
     to: ObjectId,
+    code: String,
 }
 
 #[derive(Serialize)]
@@ -156,219 +252,671 @@ struct MouseClickResponse {
 struct MessageData {
     message: String,
     username: String,
-    id: ObjectId,
     code: String,
+    #[serde(default)]
+    reply_to: Option<ObjectId>,
+    #[serde(default)]
+    to: Option<Recipients>,
+}
+
+// A private message may name one recipient or several.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Recipients {
+    One(ObjectId),
+    Many(Vec<ObjectId>),
+}
+
+impl Recipients {
+    fn into_vec(self) -> Vec<ObjectId> {
+        match self {
+            Recipients::One(user_id) => vec![user_id],
+            Recipients::Many(user_ids) => user_ids,
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct MessageResponse {
     message_type: String,
+    code: String,
+    message_id: ObjectId,
     message: String,
     username: String,
     id: ObjectId,
+    sent_at: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    to: Vec<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edited_at: Option<String>,
+    deleted: bool,
+    reactions: Vec<ReactionSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<ObjectId>,
+    reply_count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_reply_at: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct VideoData {
-    user_id: ObjectId,
+impl MessageResponse {
+    fn new(message: &ChatMessage) -> Self {
+        let time_string =
+            |time: Option<DateTime>| time.and_then(|time| time.try_to_rfc3339_string().ok());
+
+        MessageResponse {
+            message_type: "message".to_string(),
+            code: message.room_code.clone(),
+            message_id: message._id.unwrap_or_default(),
+            message: message.message.clone(),
+            username: message.username.clone(),
+            id: message.sender_id,
+            sent_at: time_string(Some(message.sent_at)).unwrap_or_default(),
+            to: message.recipients.clone(),
+            edited_at: time_string(message.edited_at),
+            deleted: message.is_deleted(),
+            reactions: ReactionSummary::all(&message.reactions),
+            reply_to: message.reply_to,
+            reply_count: message.reply_count,
+            last_reply_at: time_string(message.last_reply_at),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MessageRejected {
+    message_type: String,
     code: String,
-    host: bool,
+    reason: String,
 }
 
 #[derive(Serialize)]
-struct VideoResponse {
+struct ThreadUpdated {
     message_type: String,
-    user_id: ObjectId,
-    host: bool,
+    code: String,
+    message_id: ObjectId,
+    reply_count: u32,
+    last_reply_at: Option<String>,
 }
 
 #[derive(Deserialize)]
-struct LeaveRoomData {
+struct EditMessageData {
     code: String,
-    user_id: ObjectId,
+    message_id: ObjectId,
+    message: String,
 }
 
-#[derive(Serialize)]
-struct HostLeftresponse {
-    message_type: String,
-    host: ObjectId,
-    username: String,
+#[derive(Deserialize)]
+struct DeleteMessageData {
+    code: String,
+    message_id: ObjectId,
 }
 
 #[derive(Serialize)]
-struct ParticipantLeft {
+struct MessageUpdated {
     message_type: String,
-    user: ObjectId,
+    code: String,
+    message_id: ObjectId,
+    message: String,
+    edited_at: String,
 }
 
 #[derive(Deserialize)]
-struct RequestAccessData {
-    to: ObjectId,
-    from: ObjectId,
-    username: String,
+struct ReactionData {
+    code: String,
+    message_id: ObjectId,
+    emoji: String,
 }
 
 #[derive(Serialize)]
-struct RequestAccessResponse {
+struct ReactionSummary {
+    emoji: String,
+    count: usize,
+    user_ids: Vec<ObjectId>,
+}
+
+impl ReactionSummary {
+    fn all(reactions: &[Reaction]) -> Vec<Self> {
+        reactions
+            .iter()
+            .map(|reaction| ReactionSummary {
+                emoji: reaction.emoji.clone(),
+                count: reaction.user_ids.len(),
+                user_ids: reaction.user_ids.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct ReactionsUpdated {
     message_type: String,
-    user_id: ObjectId,
-    username: String,
+    code: String,
+    message_id: ObjectId,
+    reactions: Vec<ReactionSummary>,
 }
 
 #[derive(Deserialize)]
-struct AccessData {
+struct MeetingReactionData {
     code: String,
-    user_id: ObjectId,
-    username: String,
+    emoji: String,
 }
 
 #[derive(Serialize)]
-struct AccessResponse {
+struct MeetingReaction {
     message_type: String,
+    code: String,
     user_id: ObjectId,
-    username: String,
+    emoji: String,
 }
 
-pub async fn handler(ws: WebSocketUpgrade, State(state): State<SharedState>) -> Response {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+#[derive(Serialize)]
+struct RateLimited {
+    message_type: String,
+    action: String,
 }
 
-async fn handle_socket(socket: WebSocket, state: SharedState) {
-    let (sender, receiver) = socket.split();
-    let db = state.db.clone();
-    let ws_state = state.ws_state.clone();
+#[derive(Serialize)]
+struct MessageDeleted {
+    message_type: String,
+    code: String,
+    message_id: ObjectId,
+    deleted_by: ObjectId,
+}
 
-    let socket_id = Uuid::new_v4();
+#[derive(Serialize)]
+struct ChatHistory {
+    message_type: String,
+    code: String,
+    messages: Vec<MessageResponse>,
+    has_more: bool,
+    private_messages: Vec<MessageResponse>,
+    has_more_private: bool,
+    read_markers: Vec<ReadMarkerView>,
+    private_read_markers: Vec<ReadMarkerView>,
+    unread_count: u64,
+}
 
-    {
-        let mut sockets = ws_state.sockets.lock().await;
-        sockets.insert(socket_id, Arc::new(Mutex::new(sender)));
+#[derive(Serialize)]
+struct ReadMarkerView {
+    user_id: ObjectId,
+    message_id: ObjectId,
+    read_at: String,
+}
+
+impl ReadMarkerView {
+    fn new(marker: &ReadMarker) -> Self {
+        ReadMarkerView {
+            user_id: marker.user_id,
+            message_id: marker.message_id,
+            read_at: marker.read_at.try_to_rfc3339_string().unwrap_or_default(),
+        }
     }
+}
 
-    task::spawn(handle_rooms(receiver, socket_id, db, ws_state));
+#[derive(Deserialize)]
+struct MarkReadData {
+    code: String,
+    message_id: ObjectId,
 }
 
-async fn handle_rooms(
-    mut receiver: SplitStream<WebSocket>,
-    socket_id: Uuid,
-    db: Arc<Database>,
-    ws_state: Arc<AppState>,
-) {
-    while let Some(result) = receiver.next().await {
-        match result {
-            Ok(Message::Text(text)) => {
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                    if let Some(message_type) = json["type"].as_str() {
-                        match message_type {
-                            "join-room" => {
-                                let data: JoinRoomData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => continue,
-                                    };
+#[derive(Serialize)]
+struct ReadUpdated {
+    message_type: String,
+    code: String,
+    user_id: ObjectId,
+    message_id: ObjectId,
+    private: bool,
+}
 
-                                let token = data.access_token;
+#[derive(Deserialize)]
+struct TypingData {
+    code: String,
+}
 
-                                let claim: AccessClaims = match verify_access_token(&token) {
-                                    Ok(claim) => claim,
-                                    Err(_) => continue,
-                                };
+#[derive(Serialize)]
+struct TypingResponse {
+    message_type: String,
+    code: String,
+    user_id: ObjectId,
+    typing: bool,
+}
 
-                                let oid: ObjectId = match ObjectId::parse_str(&claim.sub) {
-                                    Ok(id) => id,
-                                    Err(_) => continue,
-                                };
+#[derive(Deserialize)]
+struct VideoData {
+    code: String,
+}
 
-                                {
-                                    let mut user_sockets = ws_state.user_sockets.lock().await;
-                                    user_sockets.insert(oid, socket_id);
-                                }
+#[derive(Serialize)]
+struct VideoResponse {
+    message_type: String,
+    user_id: ObjectId,
+    host: bool,
+}
 
-                                let room: Room = match Database::get_room_by_code(
-                                    db.clone(),
-                                    &data.code,
-                                )
-                                .await
-                                {
-                                    Ok(Some(room)) => room,
-                                    _ => {
-                                        let response = RoomNotFound {
-                                            message_type: "room-not-found".to_string(),
-                                        };
+#[derive(Deserialize)]
+struct LeaveRoomData {
+    code: String,
+    user_id: ObjectId,
+    #[serde(default)]
+    successor: Option<ObjectId>,
+}
 
-                                        let response_text =
-                                            serde_json::to_string(&response).unwrap();
+#[derive(Deserialize)]
+struct SetRoleData {
+    code: String,
+    user_id: ObjectId,
+    role: Role,
+}
 
-                                        let sender_arc = {
-                                            let sockets = ws_state.sockets.lock().await;
-                                            sockets.get(&socket_id).cloned()
-                                        };
+#[derive(Serialize)]
+struct RoleChanged {
+    message_type: String,
+    code: String,
+    user_id: ObjectId,
+    role: Role,
+}
 
-                                        if let Some(sender_arc) = sender_arc {
-                                            let mut sender = sender_arc.lock().await;
-                                            if let Err(err) = sender
-                                                .send(Message::Text(response_text.into()))
-                                                .await
-                                            {
-                                                eprintln!(
-                                                    "Failed to send 'room-not-found' message to user {}: {}",
-                                                    socket_id, err
-                                                );
-                                            }
-                                        }
+#[derive(Deserialize)]
+struct RemoveParticipantData {
+    code: String,
+    user_id: ObjectId,
+    #[serde(default)]
+    reason: Option<String>,
+}
 
-                                        continue;
-                                    }
-                                };
+#[derive(Serialize)]
+struct RemovedResponse {
+    message_type: String,
+    code: String,
+    reason: String,
+    banned: bool,
+}
 
-                                let user: User =
-                                    match Database::get_user_by_id(db.clone(), oid).await {
-                                        Ok(Some(user)) => user,
-                                        _ => continue,
-                                    };
+#[derive(Deserialize)]
+struct UnbanData {
+    code: String,
+    user_id: ObjectId,
+}
 
-                                let response: JoinRoomResponse;
-                                let host_id = if oid == room.host_id {
-                                    response = JoinRoomResponse {
-                                        message_type: "host-joined".to_string(),
-                                        user_id: oid,
-                                        username: user.username,
-                                    };
-                                    oid
-                                } else {
-                                    let host =
-                                        match Database::get_user_by_id(db.clone(), room.host_id)
-                                            .await
-                                        {
-                                            Ok(Some(host)) => host,
-                                            _ => continue,
-                                        };
+#[derive(Serialize)]
+struct ParticipantUnbanned {
+    message_type: String,
+    code: String,
+    user_id: ObjectId,
+}
 
-                                    let host_id = match host._id {
-                                        Some(id) => id,
-                                        None => continue,
-                                    };
+#[derive(Deserialize)]
+struct TransferHostData {
+    code: String,
+    user_id: ObjectId,
+}
 
-                                    response = JoinRoomResponse {
-                                        message_type: "join-request".to_string(),
-                                        user_id: oid,
-                                        username: user.username,
-                                    };
-                                    host_id
-                                };
+#[derive(Serialize)]
+struct HostChanged {
+    message_type: String,
+    code: String,
+    host: ObjectId,
+    previous_host: ObjectId,
+    username: String,
+}
 
-                                let response_text = serde_json::to_string(&response).unwrap();
+#[derive(Serialize)]
+struct RoomStateSnapshot {
+    message_type: String,
+    code: String,
+    host: ObjectId,
+    members: Vec<MemberState>,
+    hand_queue: Vec<ObjectId>,
+}
 
-                                let sender_id = {
-                                    let user_sockets = ws_state.user_sockets.lock().await;
-                                    user_sockets.get(&host_id).cloned()
-                                };
+#[derive(Deserialize)]
+struct HandData {
+    code: String,
+    // Someone else's hand, which only moderators may lower.
+    #[serde(default)]
+    user_id: Option<ObjectId>,
+}
 
-                                if let Some(sender_id) = sender_id {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(&sender_id).cloned()
-                                    };
+#[derive(Deserialize)]
+struct CallNextData {
+    code: String,
+}
+
+#[derive(Serialize)]
+struct HandQueue {
+    message_type: String,
+    code: String,
+    queue: Vec<ObjectId>,
+}
+
+#[derive(Serialize)]
+struct CalledOn {
+    message_type: String,
+    code: String,
+    user_id: ObjectId,
+    username: String,
+}
+
+#[derive(Serialize)]
+struct MemberUpdated {
+    message_type: String,
+    code: String,
+    user_id: ObjectId,
+    changes: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct AudioData {
+    code: String,
+}
+
+#[derive(Serialize)]
+struct AudioResponse {
+    message_type: String,
+    user_id: ObjectId,
+}
+
+#[derive(Deserialize)]
+struct MuteData {
+    code: String,
+    #[serde(default)]
+    user_id: Option<ObjectId>,
+}
+
+#[derive(Deserialize)]
+struct MemberTargetData {
+    code: String,
+    user_id: ObjectId,
+}
+
+#[derive(Serialize)]
+struct MuteRequested {
+    message_type: String,
+    code: String,
+    by: ObjectId,
+}
+
+#[derive(Serialize)]
+struct UnmuteRequested {
+    message_type: String,
+    code: String,
+    user_id: ObjectId,
+    username: String,
+}
+
+#[derive(Serialize)]
+struct UnmuteAllowed {
+    message_type: String,
+    code: String,
+}
+
+#[derive(Serialize)]
+struct PermissionDenied {
+    message_type: String,
+    action: String,
+}
+
+#[derive(Serialize)]
+struct RoomEnded {
+    message_type: String,
+    code: String,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct MeetingEndingSoon {
+    message_type: String,
+    code: String,
+    ends_at: String,
+    seconds_left: i64,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct HostLeftresponse {
+    message_type: String,
+    host: ObjectId,
+    username: String,
+}
+
+#[derive(Serialize)]
+struct ParticipantLeft {
+    message_type: String,
+    user: ObjectId,
+}
+
+#[derive(Deserialize)]
+struct RequestAccessData {
+    to: ObjectId,
+    from: ObjectId,
+    username: String,
+    code: String,
+}
+
+#[derive(Serialize)]
+struct RequestAccessResponse {
+    message_type: String,
+    user_id: ObjectId,
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct AccessData {
+    code: String,
+    user_id: ObjectId,
+    username: String,
+}
+
+#[derive(Serialize)]
+struct AccessResponse {
+    message_type: String,
+    user_id: ObjectId,
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct CreateBreakoutsData {
+    code: String,
+    count: usize,
+    #[serde(default)]
+    names: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BreakoutAssignment {
+    user_id: ObjectId,
+    breakout: String,
+}
+
+// Either explicit `assignments` or `random`, which spreads every participant
+// evenly over the breakouts.
+#[derive(Deserialize)]
+struct AssignBreakoutsData {
+    code: String,
+    #[serde(default)]
+    assignments: Vec<BreakoutAssignment>,
+    #[serde(default)]
+    random: bool,
+    #[serde(default)]
+    duration_mins: Option<u32>,
+}
+
+// Without `breakout` the host goes back to the main room.
+#[derive(Deserialize)]
+struct VisitBreakoutData {
+    code: String,
+    #[serde(default)]
+    breakout: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BreakoutReturnData {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct BreakoutBroadcastData {
+    code: String,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct BreakoutView {
+    code: String,
+    name: String,
+    members: Vec<ObjectId>,
+}
+
+#[derive(Serialize)]
+struct BreakoutsUpdated {
+    message_type: String,
+    code: String,
+    breakouts: Vec<BreakoutView>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ends_at: Option<String>,
+}
+
+#[derive(Serialize)]
+struct MovedToBreakout {
+    message_type: String,
+    code: String,
+    parent_code: String,
+    name: String,
+    members: Vec<ObjectId>,
+}
+
+#[derive(Serialize)]
+struct BreakoutsClosed {
+    message_type: String,
+    code: String,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct BreakoutBroadcast {
+    message_type: String,
+    code: String,
+    from: ObjectId,
+    message: String,
+}
+
+pub async fn handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    State(state): State<SharedState>,
+) -> Response {
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    ws.on_upgrade(|socket| handle_socket(socket, state, user_agent))
+}
+
+async fn handle_socket(socket: WebSocket, state: SharedState, user_agent: Option<String>) {
+    let (sender, receiver) = socket.split();
+    let db = state.db.clone();
+    let ws_state = state.ws_state.clone();
+
+    let socket_id = Uuid::new_v4();
+
+    {
+        let mut sockets = ws_state.sockets.lock().await;
+        sockets.insert(socket_id, Arc::new(Mutex::new(sender)));
+    }
+
+    task::spawn(handle_rooms(receiver, socket_id, db, ws_state, user_agent));
+}
+
+async fn handle_rooms(
+    mut receiver: SplitStream<WebSocket>,
+    socket_id: Uuid,
+    db: Arc<Database>,
+    ws_state: Arc<AppState>,
+    user_agent: Option<String>,
+) {
+    let mut current_user: Option<ObjectId> = None;
+
+    while let Some(result) = receiver.next().await {
+        match result {
+            Ok(Message::Text(text)) => {
+                if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text)
+                    && let Some(message_type) = json["type"].as_str()
+                {
+                    match message_type {
+                        "join-room" => {
+                            let data: JoinRoomData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let token = data.access_token;
+                            let device = data.device.or_else(|| user_agent.clone());
+
+                            let invite = match data.invite_token.as_deref().map(verify_invite_token)
+                            {
+                                Some(Ok(claims)) => Some(claims),
+                                Some(Err(_)) => {
+                                    send_join_denied(&ws_state, &socket_id, "invalid-invite").await;
+                                    continue;
+                                }
+                                None => None,
+                            };
+                            let code = match (data.code, &invite) {
+                                (Some(code), Some(invite)) if code != invite.room => {
+                                    send_join_denied(&ws_state, &socket_id, "invalid-invite").await;
+                                    continue;
+                                }
+                                (Some(code), _) => code,
+                                (None, Some(invite)) => invite.room.clone(),
+                                (None, None) => continue,
+                            };
+
+                            // Account holders present an access token; guests present a
+                            // guest token that is scoped to the room they asked to join.
+                            let (claim_sub, claim_email, guest_claim): (
+                                String,
+                                Option<String>,
+                                Option<GuestClaims>,
+                            ) = match verify_access_token(&token) {
+                                Ok(claim) => (claim.sub, Some(claim.email), None),
+                                Err(_) => match verify_guest_token(&token) {
+                                    Ok(claim) if claim.room == code => {
+                                        (claim.sub.clone(), None, Some(claim))
+                                    }
+                                    _ => continue,
+                                },
+                            };
+
+                            let oid: ObjectId = match ObjectId::parse_str(&claim_sub) {
+                                Ok(id) => id,
+                                Err(_) => continue,
+                            };
+
+                            {
+                                let mut user_sockets = ws_state.user_sockets.lock().await;
+                                user_sockets.insert(oid, socket_id);
+                            }
+                            current_user = Some(oid);
+
+                            let room: Room = match Database::get_room_by_code(db.clone(), &code)
+                                .await
+                            {
+                                Ok(Some(room)) => room,
+                                _ => {
+                                    let response = RoomNotFound {
+                                        message_type: "room-not-found".to_string(),
+                                    };
+
+                                    let response_text = serde_json::to_string(&response).unwrap();
+
+                                    let sender_arc = {
+                                        let sockets = ws_state.sockets.lock().await;
+                                        sockets.get(&socket_id).cloned()
+                                    };
 
                                     if let Some(sender_arc) = sender_arc {
                                         let mut sender = sender_arc.lock().await;
@@ -376,91 +924,1921 @@ async fn handle_rooms(
                                             sender.send(Message::Text(response_text.into())).await
                                         {
                                             eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
+                                                "Failed to send 'room-not-found' message to user {}: {}",
+                                                socket_id, err
                                             );
                                         }
                                     }
+
+                                    continue;
+                                }
+                            };
+
+                            // Breakouts are entered from their parent room.
+                            if room.parent_code.is_some() {
+                                send_join_denied(&ws_state, &socket_id, "breakout-room").await;
+                                continue;
+                            }
+
+                            // Scheduled meetings only open shortly before their start.
+                            if let Ok(Some(meeting)) =
+                                Database::get_meeting_by_code(db.clone(), &room.code).await
+                            {
+                                let now = DateTime::now();
+                                let reason = if meeting.ended || now >= meeting.end_time {
+                                    Some("meeting-ended")
+                                } else if now < meeting.join_opens_at() {
+                                    Some("meeting-not-started")
+                                } else {
+                                    None
+                                };
+                                if let Some(reason) = reason {
+                                    let response = JoinDenied {
+                                        message_type: "join-denied".to_string(),
+                                        reason: reason.to_string(),
+                                    };
+                                    let response_text = serde_json::to_string(&response).unwrap();
+                                    send_to_socket(&ws_state, &socket_id, &response_text).await;
+                                    continue;
+                                }
+                            }
+
+                            let is_guest = guest_claim.is_some();
+                            let username = match guest_claim {
+                                Some(claim) => {
+                                    if !room.settings.guest_access {
+                                        let response = GuestRestricted {
+                                            message_type: "guest-access-disabled".to_string(),
+                                            action: "join-room".to_string(),
+                                        };
+                                        let response_text =
+                                            serde_json::to_string(&response).unwrap();
+                                        send_to_socket(&ws_state, &socket_id, &response_text).await;
+                                        continue;
+                                    }
+
+                                    ws_state
+                                        .guests
+                                        .lock()
+                                        .await
+                                        .insert(oid, claim.username.clone());
+                                    claim.username
+                                }
+                                None => match Database::get_user_by_id(db.clone(), oid).await {
+                                    Ok(Some(user)) => user.username,
+                                    _ => continue,
+                                },
+                            };
+
+                            let response: JoinRoomResponse;
+                            let host_id = if oid == room.host_id {
+                                response = JoinRoomResponse {
+                                    message_type: "host-joined".to_string(),
+                                    user_id: oid,
+                                    username: username.clone(),
+                                    guest: false,
+                                };
+                                oid
+                            } else {
+                                let host = match Database::get_user_by_id(db.clone(), room.host_id)
+                                    .await
+                                {
+                                    Ok(Some(host)) => host,
+                                    _ => continue,
+                                };
+
+                                let host_id = match host._id {
+                                    Some(id) => id,
+                                    None => continue,
+                                };
+
+                                // A valid invite is used up once per new member and may
+                                // let them skip the lobby with a preset role.
+                                let invite_link = match &invite {
+                                    Some(claims)
+                                        if !room.participants_id.contains(&oid)
+                                            && !room
+                                                .lobby
+                                                .iter()
+                                                .any(|entry| entry.user_id == oid) =>
+                                    {
+                                        let link = room
+                                            .invites
+                                            .iter()
+                                            .find(|link| link.id.to_hex() == claims.sub);
+                                        let reason = match link {
+                                            None => Some("invite-revoked"),
+                                            Some(link) if link.expires_at <= DateTime::now() => {
+                                                Some("invite-expired")
+                                            }
+                                            Some(link) if link.is_exhausted() => {
+                                                Some("invite-exhausted")
+                                            }
+                                            Some(_) => None,
+                                        };
+                                        if let Some(reason) = reason {
+                                            send_join_denied(&ws_state, &socket_id, reason).await;
+                                            continue;
+                                        }
+                                        link.cloned()
+                                    }
+                                    _ => None,
+                                };
+
+                                // A pre-approved invite gets past a lock or password, but
+                                // not past a ban or a full room.
+                                let plan = ws_state.plans.plan_for(&host);
+                                let denial = if invite_link
+                                    .as_ref()
+                                    .is_some_and(|link| link.pre_approved)
+                                {
+                                    admit_denial_reason(&room, plan, oid)
+                                } else {
+                                    join_denial_reason(&room, plan, oid, data.password.as_deref())
+                                };
+                                if let Some(reason) = denial {
+                                    let response = JoinDenied {
+                                        message_type: "join-denied".to_string(),
+                                        reason: reason.to_string(),
+                                    };
+                                    let response_text = serde_json::to_string(&response).unwrap();
+                                    send_to_socket(&ws_state, &socket_id, &response_text).await;
+                                    continue;
+                                }
+
+                                if let Some(link) = &invite_link
+                                    && !matches!(
+                                        Database::use_invite(
+                                            db.clone(),
+                                            &room.code,
+                                            link.id,
+                                            link.uses
+                                        )
+                                        .await,
+                                        Ok(true)
+                                    )
+                                {
+                                    send_join_denied(&ws_state, &socket_id, "invite-exhausted")
+                                        .await;
+                                    continue;
+                                }
+                                // Guests can only ever be attendees or viewers.
+                                let invite_role = invite_link
+                                    .as_ref()
+                                    .and_then(|link| link.role)
+                                    .filter(|role| {
+                                        !is_guest || matches!(role, Role::Attendee | Role::Viewer)
+                                    });
+
+                                if invite_link.as_ref().is_some_and(|link| link.pre_approved) {
+                                    if let Some(role) = invite_role
+                                        && let Err(err) =
+                                            Database::set_role(db.clone(), &room.code, oid, role)
+                                                .await
+                                    {
+                                        eprintln!(
+                                            "Failed to apply invite role to {}: {}",
+                                            oid, err
+                                        );
+                                        continue;
+                                    }
+                                    if let Err(err) = admit_to_room(
+                                        &db,
+                                        &ws_state,
+                                        &room,
+                                        oid,
+                                        &username,
+                                        device.clone(),
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("Failed to admit invited user {}: {}", oid, err);
+                                    }
+                                    continue;
+                                }
+
+                                let same_domain = room.settings.auto_admit_same_domain
+                                    && claim_email.as_deref().and_then(email_domain).is_some_and(
+                                        |domain| email_domain(&host.email) == Some(domain),
+                                    );
+                                if same_domain || room.settings.auto_admit_users.contains(&oid) {
+                                    if let Err(err) = admit_to_room(
+                                        &db,
+                                        &ws_state,
+                                        &room,
+                                        oid,
+                                        &username,
+                                        device.clone(),
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("Failed to auto-admit user {}: {}", oid, err);
+                                    }
+                                    continue;
+                                }
+
+                                let entry = LobbyEntry {
+                                    user_id: oid,
+                                    username: username.clone(),
+                                    guest: is_guest,
+                                    requested_at: DateTime::now(),
+                                    role: invite_role,
+                                    device: device.clone(),
+                                };
+                                if Database::add_to_lobby(db.clone(), &room.code, entry)
+                                    .await
+                                    .is_err()
+                                {
+                                    continue;
+                                }
+
+                                response = JoinRoomResponse {
+                                    message_type: "join-request".to_string(),
+                                    user_id: oid,
+                                    username: username.clone(),
+                                    guest: is_guest,
+                                };
+                                host_id
+                            };
+
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let sender_id = {
+                                let user_sockets = ws_state.user_sockets.lock().await;
+                                user_sockets.get(&host_id).cloned()
+                            };
+
+                            if let Some(sender_id) = sender_id {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(&sender_id).cloned()
+                                };
+
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
+                                    }
+                                }
+                            }
+
+                            if oid == room.host_id {
+                                record_join(&db, &ws_state, &room, oid, &username, true, device)
+                                    .await;
+                                join_member_state(&ws_state, &room, oid, &username).await;
+                                send_chat_history(&db, &ws_state, &room, oid).await;
+                            }
+
+                            // Hosts get the whole lobby when they (re)connect, and a new
+                            // joiner needs the host's list and their own position refreshed.
+                            if let Ok(Some(room)) =
+                                Database::get_room_by_code(db.clone(), &room.code).await
+                            {
+                                notify_lobby(&ws_state, &room).await;
+                            }
+                        }
+                        "request-accepted" => {
+                            let data: RequestAcceptedData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !current_user.is_some_and(|id| room.can(&id, Permission::Admit)) {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            // Only people waiting in the lobby can be admitted, and only
+                            // while the ban list and participant caps still allow it.
+                            let Some(entry) = room
+                                .lobby
+                                .iter()
+                                .find(|entry| entry.user_id == data.user_id)
+                                .cloned()
+                            else {
+                                send_admit_failed(
+                                    &ws_state,
+                                    &socket_id,
+                                    data.user_id,
+                                    "not-in-lobby",
+                                )
+                                .await;
+                                continue;
+                            };
+
+                            let plan = host_plan(&db, &ws_state, &room).await;
+                            if let Some(reason) = admit_denial_reason(&room, &plan, data.user_id) {
+                                send_admit_failed(&ws_state, &socket_id, data.user_id, reason)
+                                    .await;
+                                if let Err(err) = Database::remove_from_lobby(
+                                    db.clone(),
+                                    &room.code,
+                                    data.user_id,
+                                )
+                                .await
+                                {
+                                    eprintln!(
+                                        "Failed to remove {} from the lobby: {}",
+                                        data.user_id, err
+                                    );
+                                }
+                                let response = JoinDenied {
+                                    message_type: "join-denied".to_string(),
+                                    reason: reason.to_string(),
+                                };
+                                let response_text = serde_json::to_string(&response).unwrap();
+                                send_to_user(&ws_state, &data.user_id, &response_text).await;
+                                if let Ok(Some(room)) =
+                                    Database::get_room_by_code(db.clone(), &room.code).await
+                                {
+                                    notify_lobby(&ws_state, &room).await;
+                                }
+                                continue;
+                            }
+
+                            if let Some(role) = entry.role
+                                && Database::set_role(db.clone(), &data.code, data.user_id, role)
+                                    .await
+                                    .is_err()
+                            {
+                                continue;
+                            }
+
+                            if let Err(err) = admit_to_room(
+                                &db,
+                                &ws_state,
+                                &room,
+                                data.user_id,
+                                &entry.username,
+                                entry.device,
+                            )
+                            .await
+                            {
+                                eprintln!("Failed to admit user {}: {}", data.user_id, err);
+                                continue;
+                            }
+
+                            if let Err(err) =
+                                Database::remove_from_lobby(db.clone(), &data.code, data.user_id)
+                                    .await
+                            {
+                                eprintln!(
+                                    "Failed to remove {} from the lobby: {}",
+                                    data.user_id, err
+                                );
+                            }
+
+                            if let Ok(Some(room)) =
+                                Database::get_room_by_code(db.clone(), &data.code).await
+                            {
+                                notify_lobby(&ws_state, &room).await;
+                            }
+                        }
+
+                        "request-rejected" => {
+                            let data: RequestRejectData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_lobby_user(db.clone(), data.user_id)
+                                    .await
+                                {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !current_user.is_some_and(|id| room.can(&id, Permission::Admit)) {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            if Database::remove_from_lobby(db.clone(), &room.code, data.user_id)
+                                .await
+                                .is_err()
+                            {
+                                continue;
+                            }
+
+                            if let Ok(Some(room)) =
+                                Database::get_room_by_code(db.clone(), &room.code).await
+                            {
+                                notify_lobby(&ws_state, &room).await;
+                            }
+
+                            let response: RequestRejectResponse = RequestRejectResponse {
+                                message_type: "request-reject".to_string(),
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let user_sockets = ws_state.user_sockets.lock().await.clone();
+
+                            if let Some(sender_id) = user_sockets.get(&data.user_id) {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(sender_id).cloned()
+                                };
+
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
+                                    }
+                                }
+                            }
+                        }
+
+                        "admit-all" | "deny-all" => {
+                            let data: LobbyActionData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !current_user.is_some_and(|id| room.can(&id, Permission::Admit)) {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            if Database::clear_lobby(db.clone(), &data.code).await.is_err() {
+                                continue;
+                            }
+
+                            if message_type == "admit-all" {
+                                let plan = host_plan(&db, &ws_state, &room).await;
+                                for entry in &room.lobby {
+                                    // Re-read the room so each admitted user sees the ones
+                                    // admitted before them.
+                                    let room =
+                                        match Database::get_room_by_code(db.clone(), &data.code)
+                                            .await
+                                        {
+                                            Ok(Some(room)) => room,
+                                            _ => break,
+                                        };
+                                    if let Some(reason) =
+                                        admit_denial_reason(&room, &plan, entry.user_id)
+                                    {
+                                        let response = JoinDenied {
+                                            message_type: "join-denied".to_string(),
+                                            reason: reason.to_string(),
+                                        };
+                                        let response_text =
+                                            serde_json::to_string(&response).unwrap();
+                                        send_to_user(&ws_state, &entry.user_id, &response_text)
+                                            .await;
+                                        continue;
+                                    }
+                                    if let Some(role) = entry.role
+                                        && let Err(err) = Database::set_role(
+                                            db.clone(),
+                                            &data.code,
+                                            entry.user_id,
+                                            role,
+                                        )
+                                        .await
+                                    {
+                                        eprintln!(
+                                            "Failed to apply invite role to {}: {}",
+                                            entry.user_id, err
+                                        );
+                                    }
+                                    if let Err(err) = admit_to_room(
+                                        &db,
+                                        &ws_state,
+                                        &room,
+                                        entry.user_id,
+                                        &entry.username,
+                                        entry.device.clone(),
+                                    )
+                                    .await
+                                    {
+                                        eprintln!(
+                                            "Failed to admit user {}: {}",
+                                            entry.user_id, err
+                                        );
+                                    }
+                                }
+                            } else {
+                                let response = RequestRejectResponse {
+                                    message_type: "request-reject".to_string(),
+                                };
+                                let response_text = serde_json::to_string(&response).unwrap();
+                                for entry in &room.lobby {
+                                    send_to_user(&ws_state, &entry.user_id, &response_text).await;
+                                }
+                            }
+
+                            if let Ok(Some(room)) =
+                                Database::get_room_by_code(db.clone(), &data.code).await
+                            {
+                                notify_lobby(&ws_state, &room).await;
+                            }
+                        }
+
+                        "set-role" => {
+                            let data: SetRoleData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !current_user
+                                .is_some_and(|id| room.can(&id, Permission::AssignRoles))
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            // Hosting changes hands through succession, and guests are
+                            // limited to attendee or viewer.
+                            let is_guest = ws_state.guests.lock().await.contains_key(&data.user_id);
+                            if data.role == Role::Host
+                                || !room.participants_id.contains(&data.user_id)
+                                || (is_guest && !matches!(data.role, Role::Attendee | Role::Viewer))
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            if Database::set_role(db.clone(), &data.code, data.user_id, data.role)
+                                .await
+                                .is_err()
+                            {
+                                continue;
+                            }
+
+                            let response = RoleChanged {
+                                message_type: "role-changed".to_string(),
+                                code: data.code.clone(),
+                                user_id: data.user_id,
+                                role: data.role,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_room(&ws_state, &room, &response_text).await;
+
+                            // A new co-host needs the lobby they can now manage.
+                            if let Ok(Some(room)) =
+                                Database::get_room_by_code(db.clone(), &data.code).await
+                            {
+                                notify_lobby(&ws_state, &room).await;
+                            }
+                        }
+
+                        "remove-participant" | "ban-participant" => {
+                            let data: RemoveParticipantData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let actor = match current_user {
+                                Some(id) if room.can(&id, Permission::Kick) => id,
+                                _ => {
+                                    send_permission_denied(&ws_state, &socket_id, message_type)
+                                        .await;
+                                    continue;
+                                }
+                            };
+
+                            // Nobody can remove the host, and only the host can remove a
+                            // co-host.
+                            let target_role = room.role_of(&data.user_id);
+                            if data.user_id == actor
+                                || target_role == Role::Host
+                                || (target_role == Role::CoHost && actor != room.host_id)
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            let banned = message_type == "ban-participant";
+                            if banned {
+                                let entry = BanEntry {
+                                    user_id: data.user_id,
+                                    banned_by: actor,
+                                    reason: data.reason.clone(),
+                                    banned_at: DateTime::now(),
+                                };
+                                if Database::ban_user(db.clone(), &data.code, entry)
+                                    .await
+                                    .is_err()
+                                {
+                                    continue;
+                                }
+                            }
+
+                            let in_room = room.participants_id.contains(&data.user_id);
+                            if in_room {
+                                if Database::remove_participant_from_room(
+                                    db.clone(),
+                                    &data.code,
+                                    data.user_id,
+                                )
+                                .await
+                                .is_err()
+                                {
+                                    continue;
+                                }
+                                if Database::remove_role(db.clone(), &data.code, data.user_id)
+                                    .await
+                                    .is_err()
+                                {
+                                    continue;
+                                }
+                            }
+
+                            let in_lobby =
+                                room.lobby.iter().any(|entry| entry.user_id == data.user_id);
+                            if in_lobby
+                                && Database::remove_from_lobby(db.clone(), &data.code, data.user_id)
+                                    .await
+                                    .is_err()
+                            {
+                                continue;
+                            }
+
+                            remove_member_state(&ws_state, &data.code, &data.user_id).await;
+                            ws_state.guests.lock().await.remove(&data.user_id);
+                            let reason = if banned { "banned" } else { "removed" };
+                            record_leave(&db, &room, data.user_id, reason).await;
+                            drop_from_breakouts(&db, &ws_state, &room, data.user_id).await;
+
+                            let response = RemovedResponse {
+                                message_type: "removed".to_string(),
+                                code: data.code.clone(),
+                                reason: data.reason.clone().unwrap_or_else(|| {
+                                    if banned { "banned" } else { "removed-by-host" }.to_string()
+                                }),
+                                banned,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_user(&ws_state, &data.user_id, &response_text).await;
+
+                            // Peers tear down their connection to the removed user the same
+                            // way they do when someone leaves.
+                            if in_room {
+                                let mut remaining = room.clone();
+                                remaining.participants_id.retain(|id| *id != data.user_id);
+
+                                let response = ParticipantLeft {
+                                    message_type: "participant-left".to_string(),
+                                    user: data.user_id,
+                                };
+                                let response_text = serde_json::to_string(&response).unwrap();
+                                send_to_room(&ws_state, &remaining, &response_text).await;
+                            }
+
+                            if in_lobby
+                                && let Ok(Some(room)) =
+                                    Database::get_room_by_code(db.clone(), &data.code).await
+                            {
+                                notify_lobby(&ws_state, &room).await;
+                            }
+                        }
+
+                        "unban-participant" => {
+                            let data: UnbanData = match serde_json::from_value(json["data"].clone())
+                            {
+                                Ok(d) => d,
+                                Err(err) => {
+                                    eprintln!("Invalid {} payload: {}", message_type, err);
+                                    continue;
+                                }
+                            };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !current_user.is_some_and(|id| room.can(&id, Permission::Kick)) {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            if Database::unban_user(db.clone(), &data.code, data.user_id)
+                                .await
+                                .is_err()
+                            {
+                                continue;
+                            }
+
+                            let response = ParticipantUnbanned {
+                                message_type: "participant-unbanned".to_string(),
+                                code: data.code.clone(),
+                                user_id: data.user_id,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_socket(&ws_state, &socket_id, &response_text).await;
+                        }
+
+                        "transfer-host" => {
+                            let data: TransferHostData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let is_guest = ws_state.guests.lock().await.contains_key(&data.user_id);
+                            if current_user != Some(room.host_id)
+                                || !room.participants_id.contains(&data.user_id)
+                                || is_guest
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            let new_host: User =
+                                match Database::get_user_by_id(db.clone(), data.user_id).await {
+                                    Ok(Some(user)) => user,
+                                    _ => continue,
+                                };
+
+                            // The outgoing host takes over the successor's old role.
+                            let previous_role = room.role_of(&data.user_id);
+                            if !matches!(
+                                Database::update_host_id(
+                                    db.clone(),
+                                    &data.code,
+                                    data.user_id,
+                                    Some((room.host_id, previous_role)),
+                                )
+                                .await,
+                                Ok(true)
+                            ) {
+                                continue;
+                            }
+
+                            let response = HostChanged {
+                                message_type: "host-changed".to_string(),
+                                code: data.code.clone(),
+                                host: data.user_id,
+                                previous_host: room.host_id,
+                                username: new_host.username,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_room(&ws_state, &room, &response_text).await;
+
+                            for (user_id, role) in
+                                [(data.user_id, Role::Host), (room.host_id, previous_role)]
+                            {
+                                let response = RoleChanged {
+                                    message_type: "role-changed".to_string(),
+                                    code: data.code.clone(),
+                                    user_id,
+                                    role,
+                                };
+                                let response_text = serde_json::to_string(&response).unwrap();
+                                send_to_room(&ws_state, &room, &response_text).await;
+                            }
+
+                            if let Ok(Some(room)) =
+                                Database::get_room_by_code(db.clone(), &data.code).await
+                            {
+                                notify_lobby(&ws_state, &room).await;
+                            }
+                        }
+
+                        "audio-muted" | "audio-unmuted" => {
+                            let data: AudioData = match serde_json::from_value(json["data"].clone())
+                            {
+                                Ok(d) => d,
+                                Err(err) => {
+                                    eprintln!("Invalid {} payload: {}", message_type, err);
+                                    continue;
+                                }
+                            };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let user_id = match current_user {
+                                Some(id)
+                                    if id == room.host_id || room.participants_id.contains(&id) =>
+                                {
+                                    id
+                                }
+                                _ => continue,
+                            };
+
+                            let unmuting = message_type == "audio-unmuted";
+                            if unmuting {
+                                let muted_by_host = ws_state
+                                    .room_states
+                                    .lock()
+                                    .await
+                                    .get(&data.code)
+                                    .and_then(|state| state.member(&user_id))
+                                    .is_some_and(|member| member.muted_by_host);
+                                if muted_by_host {
+                                    send_permission_denied(&ws_state, &socket_id, message_type)
+                                        .await;
+                                    continue;
+                                }
+                            }
+
+                            update_member_state(&ws_state, &room, user_id, |member| {
+                                member.audio = unmuting
+                            })
+                            .await;
+
+                            let response = AudioResponse {
+                                message_type: message_type.to_string(),
+                                user_id,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_room(&ws_state, &room, &response_text).await;
+                        }
+
+                        "raise-hand" | "lower-hand" => {
+                            let data: HandData = match serde_json::from_value(json["data"].clone())
+                            {
+                                Ok(d) => d,
+                                Err(err) => {
+                                    eprintln!("Invalid {} payload: {}", message_type, err);
+                                    continue;
+                                }
+                            };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let actor = match current_user {
+                                Some(id)
+                                    if id == room.host_id || room.participants_id.contains(&id) =>
+                                {
+                                    id
+                                }
+                                _ => continue,
+                            };
+
+                            // Hands are raised by their owner; moderators may also lower
+                            // someone else's.
+                            let raising = message_type == "raise-hand";
+                            let user_id = data.user_id.unwrap_or(actor);
+                            if user_id != actor
+                                && (raising || !room.can(&actor, Permission::ManageHands))
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            set_hand(&ws_state, &room, user_id, raising).await;
+                        }
+
+                        "call-next" => {
+                            let data: CallNextData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !current_user
+                                .is_some_and(|id| room.can(&id, Permission::ManageHands))
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            let next = ws_state.room_states.lock().await.get(&room.code).and_then(
+                                |state| {
+                                    let user_id = *state.hand_queue.first()?;
+                                    Some((user_id, state.member(&user_id)?.username.clone()))
+                                },
+                            );
+                            let Some((user_id, username)) = next else {
+                                continue;
+                            };
+
+                            set_hand(&ws_state, &room, user_id, false).await;
+
+                            let response = CalledOn {
+                                message_type: "called-on".to_string(),
+                                code: room.code.clone(),
+                                user_id,
+                                username,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_room(&ws_state, &room, &response_text).await;
+                        }
+
+                        "request-mute" | "mute-all" => {
+                            let data: MuteData = match serde_json::from_value(json["data"].clone())
+                            {
+                                Ok(d) => d,
+                                Err(err) => {
+                                    eprintln!("Invalid {} payload: {}", message_type, err);
+                                    continue;
+                                }
+                            };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let actor = match current_user {
+                                Some(id) if room.can(&id, Permission::Mute) => id,
+                                _ => {
+                                    send_permission_denied(&ws_state, &socket_id, message_type)
+                                        .await;
+                                    continue;
+                                }
+                            };
+
+                            // `request-mute` names one member; `mute-all` covers everyone
+                            // who cannot mute others themselves.
+                            let targets: Vec<ObjectId> = if message_type == "request-mute" {
+                                match data.user_id {
+                                    Some(user_id) => vec![user_id],
+                                    None => continue,
+                                }
+                            } else {
+                                room.participants_id
+                                    .iter()
+                                    .filter(|id| !room.can(id, Permission::Mute))
+                                    .cloned()
+                                    .collect()
+                            };
+
+                            let response = MuteRequested {
+                                message_type: "mute-requested".to_string(),
+                                code: data.code.clone(),
+                                by: actor,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            for target in targets {
+                                if target == actor || !room.participants_id.contains(&target) {
+                                    continue;
+                                }
+
+                                update_member_state(&ws_state, &room, target, |member| {
+                                    member.audio = false;
+                                    member.muted_by_host = true;
+                                })
+                                .await;
+                                send_to_user(&ws_state, &target, &response_text).await;
+                            }
+                        }
+
+                        "request-unmute" => {
+                            let data: AudioData = match serde_json::from_value(json["data"].clone())
+                            {
+                                Ok(d) => d,
+                                Err(err) => {
+                                    eprintln!("Invalid {} payload: {}", message_type, err);
+                                    continue;
+                                }
+                            };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let user_id = match current_user {
+                                Some(id) if room.participants_id.contains(&id) => id,
+                                _ => continue,
+                            };
+
+                            let username = match ws_state
+                                .room_states
+                                .lock()
+                                .await
+                                .get(&data.code)
+                                .and_then(|state| state.member(&user_id))
+                            {
+                                Some(member) if member.muted_by_host => member.username.clone(),
+                                _ => continue,
+                            };
+
+                            let response = UnmuteRequested {
+                                message_type: "unmute-requested".to_string(),
+                                code: data.code.clone(),
+                                user_id,
+                                username,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            for moderator in room_moderators(&room) {
+                                send_to_user(&ws_state, &moderator, &response_text).await;
+                            }
+                        }
+
+                        "allow-unmute" => {
+                            let data: MemberTargetData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !current_user.is_some_and(|id| room.can(&id, Permission::Mute)) {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+                            if !room.participants_id.contains(&data.user_id) {
+                                continue;
+                            }
+
+                            update_member_state(&ws_state, &room, data.user_id, |member| {
+                                member.muted_by_host = false
+                            })
+                            .await;
+
+                            let response = UnmuteAllowed {
+                                message_type: "unmute-allowed".to_string(),
+                                code: data.code.clone(),
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_user(&ws_state, &data.user_id, &response_text).await;
+                        }
+
+                        "offer" | "answer" | "ice-candidate" => {
+                            let data: RtcConnectionData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            // Peers only negotiate media with others still in the same room,
+                            // so a removed or banned user cannot keep a connection going.
+                            let from = match current_user {
+                                Some(id)
+                                    if id == room.host_id || room.participants_id.contains(&id) =>
+                                {
+                                    id
+                                }
+                                _ => continue,
+                            };
+                            if data.to != room.host_id && !room.participants_id.contains(&data.to) {
+                                continue;
+                            }
+
+                            let response = RtcConnectionResponse {
+                                message_type: message_type.to_string(),
+                                item: data.item,
+                                from,
+                                user_id: data.to,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_user(&ws_state, &data.to, &response_text).await;
+                        }
+
+                        "mouse-move" => {
+                            let data: MouseMoveData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            if remote_control_room(
+                                &db,
+                                &ws_state,
+                                &socket_id,
+                                &data.code,
+                                current_user,
+                                data.to,
+                            )
+                            .await
+                            .is_none()
+                            {
+                                continue;
+                            }
+
+                            let response: MouseMoveResponse = MouseMoveResponse {
+                                message_type: "mouse-move".to_string(),
+                                x: data.x,
+                                y: data.y,
+                            };
+
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let sender_id = {
+                                let user_sockets = ws_state.user_sockets.lock().await;
+                                user_sockets.get(&data.to).cloned()
+                            };
+
+                            if let Some(sender_id) = sender_id {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(&sender_id).cloned()
+                                };
+
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
+                                    }
+                                }
+                            }
+                        }
+
+                        "key-press" => {
+                            let data: KeyPressData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            if remote_control_room(
+                                &db,
+                                &ws_state,
+                                &socket_id,
+                                &data.code,
+                                current_user,
+                                data.to,
+                            )
+                            .await
+                            .is_none()
+                            {
+                                continue;
+                            }
+
+                            let response: KeyPressResponse = KeyPressResponse {
+                                message_type: "key-press".to_string(),
+                                key: data.key,
+                            };
+
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let sender_id = {
+                                let user_sockets = ws_state.user_sockets.lock().await;
+                                user_sockets.get(&data.to).cloned()
+                            };
+
+                            if let Some(sender_id) = sender_id {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(&sender_id).cloned()
+                                };
+
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
+                                    }
+                                }
+                            }
+                        }
+
+                        "mouse-click" => {
+                            let data: MouseClickData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            if remote_control_room(
+                                &db,
+                                &ws_state,
+                                &socket_id,
+                                &data.code,
+                                current_user,
+                                data.to,
+                            )
+                            .await
+                            .is_none()
+                            {
+                                continue;
+                            }
+
+                            let response: MouseClickResponse = MouseClickResponse {
+                                message_type: "mouse-click".to_string(),
+                            };
+
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let sender_id = {
+                                let user_sockets = ws_state.user_sockets.lock().await;
+                                user_sockets.get(&data.to).cloned()
+                            };
+
+                            if let Some(sender_id) = sender_id {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(&sender_id).cloned()
+                                };
+
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
+                                    }
+                                }
+                            }
+                        }
+
+                        "message" => {
+                            let data: MessageData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if !room.settings.chat_enabled {
+                                let response = FeatureDisabled {
+                                    message_type: "feature-disabled".to_string(),
+                                    feature: "chat".to_string(),
+                                };
+                                let response_text = serde_json::to_string(&response).unwrap();
+                                send_to_socket(&ws_state, &socket_id, &response_text).await;
+                                continue;
+                            }
+
+                            let sender_id = match current_user {
+                                Some(id) if room.can(&id, Permission::Chat) => id,
+                                _ => {
+                                    send_permission_denied(&ws_state, &socket_id, message_type)
+                                        .await;
+                                    continue;
+                                }
+                            };
+
+                            // Private messages go to room members other than the sender,
+                            // and stay out of threads.
+                            let recipients = match data.to.map(Recipients::into_vec) {
+                                Some(mut recipients) => {
+                                    if !room.settings.private_chat_enabled {
+                                        let response = FeatureDisabled {
+                                            message_type: "feature-disabled".to_string(),
+                                            feature: "private-chat".to_string(),
+                                        };
+                                        let response_text =
+                                            serde_json::to_string(&response).unwrap();
+                                        send_to_socket(&ws_state, &socket_id, &response_text).await;
+                                        continue;
+                                    }
+
+                                    recipients.sort();
+                                    recipients.dedup();
+                                    let valid = !recipients.is_empty()
+                                        && data.reply_to.is_none()
+                                        && recipients.iter().all(|id| {
+                                            *id != sender_id
+                                                && (*id == room.host_id
+                                                    || room.participants_id.contains(id))
+                                        });
+                                    if !valid {
+                                        send_message_rejected(
+                                            &ws_state,
+                                            &socket_id,
+                                            &room.code,
+                                            "invalid-recipient",
+                                        )
+                                        .await;
+                                        continue;
+                                    }
+                                    recipients
+                                }
+                                None => vec![],
+                            };
+
+                            // A reply to a reply joins the thread of the message it answers,
+                            // so threads stay one level deep.
+                            let reply_to = match data.reply_to {
+                                Some(parent_id) => {
+                                    match Database::get_message(db.clone(), parent_id).await {
+                                        Ok(Some(parent))
+                                            if parent.room_code == room.code
+                                                && room.created_at.is_none_or(|since| {
+                                                    parent.sent_at >= since
+                                                })
+                                                && !parent.is_private()
+                                                && !parent.is_deleted() =>
+                                        {
+                                            Some(parent.reply_to.unwrap_or(parent_id))
+                                        }
+                                        _ => {
+                                            send_message_rejected(
+                                                &ws_state,
+                                                &socket_id,
+                                                &room.code,
+                                                "invalid-reply",
+                                            )
+                                            .await;
+                                            continue;
+                                        }
+                                    }
+                                }
+                                None => None,
+                            };
+
+                            let username = connected_username(&ws_state, &room.code, sender_id)
+                                .await
+                                .unwrap_or(data.username);
+                            let message = ChatMessage {
+                                _id: None,
+                                room_code: room.code.clone(),
+                                sender_id,
+                                username,
+                                message: data.message,
+                                sent_at: DateTime::now(),
+                                recipients,
+                                edited_at: None,
+                                edits: vec![],
+                                deleted_at: None,
+                                deleted_by: None,
+                                reactions: vec![],
+                                reply_to,
+                                reply_count: 0,
+                                last_reply_at: None,
+                            };
+                            let message = match Database::insert_message(db.clone(), message).await
+                            {
+                                Ok(message) => message,
+                                Err(err) => {
+                                    eprintln!("Failed to store message in {}: {}", room.code, err);
+                                    continue;
+                                }
+                            };
+
+                            let was_typing = ws_state
+                                .room_states
+                                .lock()
+                                .await
+                                .get_mut(&room.code)
+                                .is_some_and(|state| state.stop_typing(&sender_id));
+                            if was_typing {
+                                send_typing(&ws_state, &room, sender_id, false).await;
+                            }
+
+                            let response = MessageResponse::new(&message);
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_audience(&ws_state, &room, &message, &response_text).await;
+
+                            if let Some(parent_id) = reply_to {
+                                match Database::record_reply(db.clone(), parent_id, message.sent_at)
+                                    .await
+                                {
+                                    Ok(Some(parent)) => {
+                                        let response = ThreadUpdated {
+                                            message_type: "thread-updated".to_string(),
+                                            code: room.code.clone(),
+                                            message_id: parent_id,
+                                            reply_count: parent.reply_count,
+                                            last_reply_at: parent.last_reply_at.and_then(
+                                                |last_reply_at| {
+                                                    last_reply_at.try_to_rfc3339_string().ok()
+                                                },
+                                            ),
+                                        };
+                                        let response_text =
+                                            serde_json::to_string(&response).unwrap();
+                                        send_to_room(&ws_state, &room, &response_text).await;
+                                    }
+                                    Ok(None) => {}
+                                    Err(err) => {
+                                        eprintln!("Failed to count reply on {}: {}", parent_id, err)
+                                    }
+                                }
+                            }
+                        }
+                        "edit-message" => {
+                            let data: EditMessageData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+                            let message = match Database::get_message(db.clone(), data.message_id)
+                                .await
+                            {
+                                Ok(Some(message))
+                                    if message.room_code == room.code && !message.is_deleted() =>
+                                {
+                                    message
+                                }
+                                _ => continue,
+                            };
+
+                            // Only the author may edit, and only while they may still chat.
+                            if current_user != Some(message.sender_id)
+                                || !room.can(&message.sender_id, Permission::Chat)
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+                            if data.message == message.message {
+                                continue;
+                            }
+
+                            let edited =
+                                match Database::edit_message(db.clone(), &message, &data.message)
+                                    .await
+                                {
+                                    Ok(Some(edited)) => edited,
+                                    Ok(None) => continue,
+                                    Err(err) => {
+                                        eprintln!(
+                                            "Failed to edit message {}: {}",
+                                            data.message_id, err
+                                        );
+                                        continue;
+                                    }
+                                };
+
+                            let response = MessageUpdated {
+                                message_type: "message-updated".to_string(),
+                                code: room.code.clone(),
+                                message_id: data.message_id,
+                                message: edited.message,
+                                edited_at: edited
+                                    .edited_at
+                                    .and_then(|edited_at| edited_at.try_to_rfc3339_string().ok())
+                                    .unwrap_or_default(),
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_audience(&ws_state, &room, &message, &response_text).await;
+                        }
+
+                        "delete-message" => {
+                            let data: DeleteMessageData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+                            let message = match Database::get_message(db.clone(), data.message_id)
+                                .await
+                            {
+                                Ok(Some(message))
+                                    if message.room_code == room.code && !message.is_deleted() =>
+                                {
+                                    message
+                                }
+                                _ => continue,
+                            };
+
+                            // The host moderates the transcript, but cannot see private messages.
+                            let actor = match current_user {
+                                Some(id)
+                                    if id == message.sender_id
+                                        || (id == room.host_id && !message.is_private()) =>
+                                {
+                                    id
+                                }
+                                _ => {
+                                    send_permission_denied(&ws_state, &socket_id, message_type)
+                                        .await;
+                                    continue;
+                                }
+                            };
+
+                            match Database::delete_message(db.clone(), &message, actor).await {
+                                Ok(true) => {}
+                                Ok(false) => continue,
+                                Err(err) => {
+                                    eprintln!(
+                                        "Failed to delete message {}: {}",
+                                        data.message_id, err
+                                    );
+                                    continue;
+                                }
+                            }
+
+                            let response = MessageDeleted {
+                                message_type: "message-deleted".to_string(),
+                                code: room.code.clone(),
+                                message_id: data.message_id,
+                                deleted_by: actor,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_audience(&ws_state, &room, &message, &response_text).await;
+                        }
+
+                        "add-reaction" | "remove-reaction" => {
+                            let data: ReactionData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let emoji = data.emoji.trim();
+                            if emoji.is_empty() || emoji.len() > MAX_EMOJI_LEN {
+                                continue;
+                            }
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let user_id = match current_user {
+                                Some(id) if room.can(&id, Permission::Chat) => id,
+                                _ => {
+                                    send_permission_denied(&ws_state, &socket_id, message_type)
+                                        .await;
+                                    continue;
+                                }
+                            };
+
+                            let message =
+                                match Database::get_message(db.clone(), data.message_id).await {
+                                    Ok(Some(message))
+                                        if message.room_code == room.code
+                                            && message.involves(&user_id) =>
+                                    {
+                                        message
+                                    }
+                                    _ => continue,
+                                };
+
+                            let message = match Database::set_reaction(
+                                db.clone(),
+                                &message,
+                                emoji,
+                                user_id,
+                                message_type == "add-reaction",
+                            )
+                            .await
+                            {
+                                Ok(Some(message)) => message,
+                                Ok(None) => continue,
+                                Err(err) => {
+                                    eprintln!(
+                                        "Failed to update reactions on {}: {}",
+                                        data.message_id, err
+                                    );
+                                    continue;
+                                }
+                            };
+
+                            let response = ReactionsUpdated {
+                                message_type: "reactions-updated".to_string(),
+                                code: room.code.clone(),
+                                message_id: data.message_id,
+                                reactions: ReactionSummary::all(&message.reactions),
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_audience(&ws_state, &room, &message, &response_text).await;
+                        }
+
+                        "send-reaction" => {
+                            let data: MeetingReactionData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            if !MEETING_REACTIONS.contains(&data.emoji.as_str()) {
+                                continue;
+                            }
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let user_id = match current_user {
+                                Some(id)
+                                    if id == room.host_id || room.participants_id.contains(&id) =>
+                                {
+                                    id
                                 }
+                                _ => continue,
+                            };
 
-                                println!("data send to user");
+                            let allowed = ws_state
+                                .room_states
+                                .lock()
+                                .await
+                                .get_mut(&room.code)
+                                .is_some_and(|state| {
+                                    state.allow_reaction(user_id, REACTION_LIMIT, REACTION_WINDOW)
+                                });
+                            if !allowed {
+                                let response = RateLimited {
+                                    message_type: "rate-limited".to_string(),
+                                    action: message_type.to_string(),
+                                };
+                                let response_text = serde_json::to_string(&response).unwrap();
+                                send_to_socket(&ws_state, &socket_id, &response_text).await;
+                                continue;
                             }
-                            "request-accepted" => {
-                                println!("request recieved");
-                                let data: RequestAcceptedData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => continue,
-                                    };
 
-                                if let Err(_) = Database::add_participant_to_room(
-                                    db.clone(),
-                                    &data.code,
-                                    data.user_id,
-                                )
-                                .await
+                            let response = MeetingReaction {
+                                message_type: "reaction".to_string(),
+                                code: room.code.clone(),
+                                user_id,
+                                emoji: data.emoji,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_room(&ws_state, &room, &response_text).await;
+                        }
+
+                        "typing-start" | "typing-stop" => {
+                            let data: TypingData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let user_id = match current_user {
+                                Some(id)
+                                    if room.settings.chat_enabled
+                                        && room.can(&id, Permission::Chat) =>
                                 {
-                                    continue;
+                                    id
                                 }
+                                _ => continue,
+                            };
 
-                                if let Err(_) = Database::add_participant(
-                                    db.clone(),
-                                    data.code.clone(),
-                                    data.user_id,
-                                )
+                            let typing = message_type == "typing-start";
+                            let changed = ws_state
+                                .room_states
+                                .lock()
                                 .await
+                                .get_mut(&room.code)
+                                .is_some_and(|state| {
+                                    if typing {
+                                        state.start_typing(user_id, TYPING_THROTTLE)
+                                    } else {
+                                        state.stop_typing(&user_id)
+                                    }
+                                });
+                            if changed {
+                                send_typing(&ws_state, &room, user_id, typing).await;
+                            }
+                        }
+
+                        "mark-read" => {
+                            let data: MarkReadData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let user_id = match current_user {
+                                Some(id)
+                                    if id == room.host_id || room.participants_id.contains(&id) =>
                                 {
-                                    continue;
+                                    id
                                 }
+                                _ => continue,
+                            };
+
+                            let message =
+                                match Database::get_message(db.clone(), data.message_id).await {
+                                    Ok(Some(message))
+                                        if message.room_code == room.code
+                                            && message.involves(&user_id) =>
+                                    {
+                                        message
+                                    }
+                                    _ => continue,
+                                };
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+                            match Database::mark_read(
+                                db.clone(),
+                                &room.code,
+                                user_id,
+                                data.message_id,
+                                message.is_private(),
+                            )
+                            .await
+                            {
+                                Ok(true) => {}
+                                Ok(false) => continue,
+                                Err(err) => {
+                                    eprintln!("Failed to mark {} read: {}", data.message_id, err);
+                                    continue;
+                                }
+                            }
 
-                                for participant in &data.participants {
-                                    let response_to_participants =
-                                        RequestAcceptedResponseTOParticipants {
-                                            message_type: "new-participant".to_string(),
-                                            user_id: data.user_id,
-                                            username: data.username.clone(),
-                                            participant: participant.id,
-                                            host: data.host.clone(),
-                                        };
-                                    let response_to_participant_text =
-                                        serde_json::to_string(&response_to_participants).unwrap();
-                                    if let Some(sender_id) = user_sockets.get(&participant.id) {
-                                        let sender_arc = {
-                                            let sockets = ws_state.sockets.lock().await;
-                                            sockets.get(sender_id).cloned()
-                                        };
+                            let response = ReadUpdated {
+                                message_type: "read-updated".to_string(),
+                                code: room.code.clone(),
+                                user_id,
+                                message_id: data.message_id,
+                                private: message.is_private(),
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_audience(&ws_state, &room, &message, &response_text).await;
+                        }
 
-                                        if let Some(sender_arc) = sender_arc {
-                                            let mut sender = sender_arc.lock().await;
-                                            if let Err(err) = sender
-                                                .send(Message::Text(
-                                                    response_to_participant_text.clone().into(),
-                                                ))
-                                                .await
-                                            {
-                                                eprintln!(
-                                                    "Failed to send message to user {}: {}",
-                                                    sender_id, err
-                                                );
-                                            }
-                                        }
-                                    }
+                        "screen-sharing-started" | "screen-sharing-stopped" => {
+                            let data: VideoData = match serde_json::from_value(json["data"].clone())
+                            {
+                                Ok(d) => d,
+                                Err(err) => {
+                                    eprintln!("Invalid {} payload: {}", message_type, err);
+                                    continue;
                                 }
-                                println!("response sent to participants");
+                            };
 
-                                let response_to_host = RequestAcceptedResponseTOParticipants {
-                                    message_type: "new-participant".to_string(),
-                                    user_id: data.user_id,
-                                    username: data.username.clone(),
-                                    participant: data.host.id,
-                                    host: data.host.clone(),
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
                                 };
-                                let response_to_host_text =
-                                    serde_json::to_string(&response_to_host).unwrap();
 
-                                if let Some(sender_id) = user_sockets.get(&data.host.id) {
+                            // Media state is only ever reported for the sender's own user.
+                            let user_id = match current_user {
+                                Some(id)
+                                    if id == room.host_id || room.participants_id.contains(&id) =>
+                                {
+                                    id
+                                }
+                                _ => continue,
+                            };
+
+                            if message_type == "screen-sharing-started"
+                                && !room.can(&user_id, Permission::ShareScreen)
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            update_member_state(&ws_state, &room, user_id, |member| {
+                                member.screen = message_type == "screen-sharing-started"
+                            })
+                            .await;
+
+                            let response: VideoResponse = VideoResponse {
+                                message_type: message_type.to_string(),
+                                user_id,
+                                host: user_id == room.host_id,
+                            };
+
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let user_sockets = ws_state.user_sockets.lock().await.clone();
+
+                            for participant in &room.participants_id {
+                                if let Some(sender_id) = user_sockets.get(participant) {
                                     let sender_arc = {
                                         let sockets = ws_state.sockets.lock().await;
                                         sockets.get(sender_id).cloned()
@@ -469,9 +2847,7 @@ async fn handle_rooms(
                                     if let Some(sender_arc) = sender_arc {
                                         let mut sender = sender_arc.lock().await;
                                         if let Err(err) = sender
-                                            .send(Message::Text(
-                                                response_to_host_text.clone().into(),
-                                            ))
+                                            .send(Message::Text(response_text.clone().into()))
                                             .await
                                         {
                                             eprintln!(
@@ -481,56 +2857,71 @@ async fn handle_rooms(
                                         }
                                     }
                                 }
+                            }
 
-                                let response = RequestAcceptedResponse {
-                                    message_type: "participant-joined".to_string(),
-                                    user_id: data.user_id,
-                                    username: data.username.clone(),
-                                    participants: data.participants.clone(),
-                                    host: data.host.clone(),
+                            if let Some(sender_id) = user_sockets.get(&room.host_id) {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(sender_id).cloned()
                                 };
-                                let response_text = serde_json::to_string(&response).unwrap();
-
-                                if let Some(sender_id) = user_sockets.get(&data.user_id) {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(sender_id).cloned()
-                                    };
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
                                     }
                                 }
-
-                                println!("response sent")
                             }
+                        }
 
-                            "request-rejected" => {
-                                let data: RequestRejectData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+                        "video-started" | "video-stopped" => {
+                            let data: VideoData = match serde_json::from_value(json["data"].clone())
+                            {
+                                Ok(d) => d,
+                                Err(err) => {
+                                    eprintln!("Invalid {} payload: {}", message_type, err);
+                                    continue;
+                                }
+                            };
 
-                                let response: RequestRejectResponse = RequestRejectResponse {
-                                    message_type: "request-reject".to_string(),
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
                                 };
-                                let response_text = serde_json::to_string(&response).unwrap();
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+                            // Media state is only ever reported for the sender's own user.
+                            let user_id = match current_user {
+                                Some(id)
+                                    if id == room.host_id || room.participants_id.contains(&id) =>
+                                {
+                                    id
+                                }
+                                _ => continue,
+                            };
+
+                            update_member_state(&ws_state, &room, user_id, |member| {
+                                member.video = message_type == "video-started"
+                            })
+                            .await;
 
-                                if let Some(sender_id) = user_sockets.get(&data.user_id) {
+                            let response: VideoResponse = VideoResponse {
+                                message_type: message_type.to_string(),
+                                user_id,
+                                host: user_id == room.host_id,
+                            };
+
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let user_sockets = ws_state.user_sockets.lock().await.clone();
+
+                            for participant in &room.participants_id {
+                                if let Some(sender_id) = user_sockets.get(participant) {
                                     let sender_arc = {
                                         let sockets = ws_state.sockets.lock().await;
                                         sockets.get(sender_id).cloned()
@@ -538,8 +2929,9 @@ async fn handle_rooms(
 
                                     if let Some(sender_arc) = sender_arc {
                                         let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
+                                        if let Err(err) = sender
+                                            .send(Message::Text(response_text.clone().into()))
+                                            .await
                                         {
                                             eprintln!(
                                                 "Failed to send message to user {}: {}",
@@ -550,82 +2942,184 @@ async fn handle_rooms(
                                 }
                             }
 
-                            "offer" | "answer" | "ice-candidate" => {
-                                let data: RtcConnectionData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+                            if let Some(sender_id) = user_sockets.get(&room.host_id) {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(sender_id).cloned()
+                                };
+
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
+                                    }
+                                }
+                            }
+                        }
 
-                                let response = RtcConnectionResponse {
-                                    message_type: message_type.to_string(),
-                                    item: data.item,
-                                    from: data.user_id,
-                                    user_id: data.to,
+                        "leave-room" => {
+                            let data: LeaveRoomData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
                                 };
-                                let response_text = serde_json::to_string(&response).unwrap();
 
-                                let sender_id = {
-                                    let user_sockets = ws_state.user_sockets.lock().await;
-                                    user_sockets.get(&data.to).cloned()
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
                                 };
 
-                                if let Some(sender_id) = sender_id {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(&sender_id).cloned()
-                                    };
+                            // Sockets can only take their own user out of a room.
+                            let leaving_id = match current_user {
+                                Some(id) if id == data.user_id => id,
+                                _ => continue,
+                            };
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
+                            let response_text: String;
+
+                            if leaving_id == room.host_id {
+                                if room.participants_id.is_empty() {
+                                    if let Err(err) =
+                                        close_room(db.clone(), &ws_state, &room, "host-left").await
+                                    {
+                                        eprintln!("Failed to close room {}: {}", room.code, err);
+                                    }
+                                    continue;
+                                }
+
+                                // The leaving host may name a successor; otherwise a co-host
+                                // takes over before anyone else. Guests can never host, so a
+                                // room left with only guests is closed.
+                                let successor = {
+                                    let guests = ws_state.guests.lock().await;
+                                    let eligible = |id: &ObjectId| {
+                                        room.participants_id.contains(id)
+                                            && !guests.contains_key(id)
+                                    };
+                                    data.successor
+                                        .filter(|id| eligible(id))
+                                        .or_else(|| {
+                                            room.roles
+                                                .iter()
+                                                .filter(|assignment| {
+                                                    assignment.role == Role::CoHost
+                                                })
+                                                .map(|assignment| assignment.user_id)
+                                                .find(|id| eligible(id))
+                                        })
+                                        .or_else(|| {
+                                            room.participants_id
+                                                .iter()
+                                                .find(|id| eligible(id))
+                                                .cloned()
+                                        })
+                                };
+                                let user_id = match successor {
+                                    Some(id) => id,
+                                    None => {
                                         if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
+                                            close_room(db.clone(), &ws_state, &room, "host-left")
+                                                .await
                                         {
                                             eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
+                                                "Failed to close room {}: {}",
+                                                room.code, err
                                             );
                                         }
+                                        continue;
                                     }
+                                };
+                                if let Err(err) =
+                                    Database::update_host_id(db.clone(), &data.code, user_id, None)
+                                        .await
+                                {
+                                    eprintln!(
+                                        "Failed to hand {} to {}: {}",
+                                        data.code, user_id, err
+                                    );
+                                    continue;
                                 }
-                            }
-
-                            "mouse-move" => {
-                                let data: MouseMoveData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
+                                remove_member_state(&ws_state, &data.code, &leaving_id).await;
+                                record_leave(&db, &room, leaving_id, "left").await;
+
+                                let response = RoleChanged {
+                                    message_type: "role-changed".to_string(),
+                                    code: data.code.clone(),
+                                    user_id,
+                                    role: Role::Host,
+                                };
+                                let role_text = serde_json::to_string(&response).unwrap();
+                                send_to_room(&ws_state, &room, &role_text).await;
+                                let user: User =
+                                    match Database::get_user_by_id(db.clone(), user_id).await {
+                                        Ok(Some(user)) => user,
+                                        _ => continue,
                                     };
-
-                                let response: MouseMoveResponse = MouseMoveResponse {
-                                    message_type: "mouse-move".to_string(),
-                                    x: data.x,
-                                    y: data.y,
+                                let response = HostLeftresponse {
+                                    message_type: "host-left".to_string(),
+                                    host: user_id,
+                                    username: user.username,
                                 };
 
-                                let response_text = serde_json::to_string(&response).unwrap();
+                                response_text = serde_json::to_string(&response).unwrap();
+                            } else {
+                                if let Err(err) = Database::remove_participant_from_room(
+                                    db.clone(),
+                                    &data.code,
+                                    leaving_id,
+                                )
+                                .await
+                                {
+                                    eprintln!(
+                                        "Failed to remove {} from {}: {}",
+                                        leaving_id, data.code, err
+                                    );
+                                    continue;
+                                }
+
+                                if Database::remove_role(db.clone(), &data.code, leaving_id)
+                                    .await
+                                    .is_err()
+                                {
+                                    continue;
+                                }
 
-                                let sender_id = {
-                                    let user_sockets = ws_state.user_sockets.lock().await;
-                                    user_sockets.get(&data.to).cloned()
+                                remove_member_state(&ws_state, &data.code, &leaving_id).await;
+                                ws_state.guests.lock().await.remove(&leaving_id);
+                                record_leave(&db, &room, leaving_id, "left").await;
+                                drop_from_breakouts(&db, &ws_state, &room, leaving_id).await;
+
+                                let response = ParticipantLeft {
+                                    message_type: "participant-left".to_string(),
+                                    user: leaving_id,
                                 };
 
-                                if let Some(sender_id) = sender_id {
+                                response_text = serde_json::to_string(&response).unwrap();
+                            }
+
+                            let user_sockets = ws_state.user_sockets.lock().await.clone();
+
+                            for participant in &room.participants_id {
+                                if let Some(sender_id) = user_sockets.get(participant) {
                                     let sender_arc = {
                                         let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(&sender_id).cloned()
+                                        sockets.get(sender_id).cloned()
                                     };
 
                                     if let Some(sender_arc) = sender_arc {
                                         let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
+                                        if let Err(err) = sender
+                                            .send(Message::Text(response_text.clone().into()))
+                                            .await
                                         {
                                             eprintln!(
                                                 "Failed to send message to user {}: {}",
@@ -636,143 +3130,394 @@ async fn handle_rooms(
                                 }
                             }
 
-                            "key-press" => {
-                                let data: KeyPressData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
-
-                                let response: KeyPressResponse = KeyPressResponse {
-                                    message_type: "key-press".to_string(),
-                                    key: data.key,
+                            if let Some(sender_id) = user_sockets.get(&room.host_id) {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(sender_id).cloned()
                                 };
 
-                                let response_text = serde_json::to_string(&response).unwrap();
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        "create-breakouts" => {
+                            let data: CreateBreakoutsData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
 
-                                let sender_id = {
-                                    let user_sockets = ws_state.user_sockets.lock().await;
-                                    user_sockets.get(&data.to).cloned()
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
                                 };
 
-                                if let Some(sender_id) = sender_id {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(&sender_id).cloned()
-                                    };
+                            if current_user != Some(room.host_id) || room.parent_code.is_some() {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+                            if !(1..=MAX_BREAKOUTS).contains(&data.count) {
+                                continue;
+                            }
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
+                            // Creating breakouts again replaces the current set.
+                            if let Err(err) =
+                                close_breakouts(&db, &ws_state, &room, "replaced").await
+                            {
+                                eprintln!("Failed to close breakouts of {}: {}", room.code, err);
+                                continue;
+                            }
+
+                            for index in 0..data.count {
+                                let name = data
+                                    .names
+                                    .get(index)
+                                    .map(|name| name.trim().to_string())
+                                    .filter(|name| !name.is_empty())
+                                    .unwrap_or_else(|| format!("Room {}", index + 1));
+                                let code = format!("{}-{}", room.code, index + 1);
+                                if let Err(err) =
+                                    Database::create_breakout(db.clone(), &room, code, name).await
+                                {
+                                    eprintln!(
+                                        "Failed to create breakout of {}: {}",
+                                        room.code, err
+                                    );
+                                }
+                            }
+
+                            send_breakouts_updated(&db, &ws_state, &room.code).await;
+                        }
+
+                        "assign-breakouts" => {
+                            let data: AssignBreakoutsData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
                                     }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if current_user != Some(room.host_id) || room.parent_code.is_some() {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            let breakouts =
+                                match Database::get_breakouts(db.clone(), &room.code).await {
+                                    Ok(breakouts) if !breakouts.is_empty() => breakouts,
+                                    _ => continue,
+                                };
+
+                            let assignments: Vec<(ObjectId, String)> = if data.random {
+                                let mut members = room.participants_id.clone();
+                                members.shuffle(&mut rand::thread_rng());
+                                members
+                                    .into_iter()
+                                    .zip(
+                                        breakouts
+                                            .iter()
+                                            .map(|breakout| breakout.code.clone())
+                                            .cycle(),
+                                    )
+                                    .collect()
+                            } else {
+                                data.assignments
+                                    .into_iter()
+                                    .map(|assignment| (assignment.user_id, assignment.breakout))
+                                    .collect()
+                            };
+
+                            for (user_id, breakout_code) in assignments {
+                                if !room.participants_id.contains(&user_id)
+                                    || !breakouts
+                                        .iter()
+                                        .any(|breakout| breakout.code == breakout_code)
+                                {
+                                    continue;
+                                }
+                                if let Err(err) = move_to_breakout(
+                                    &db,
+                                    &ws_state,
+                                    &room,
+                                    &breakouts,
+                                    user_id,
+                                    &breakout_code,
+                                )
+                                .await
+                                {
+                                    eprintln!("Failed to move {} to a breakout: {}", user_id, err);
                                 }
                             }
 
-                            "mouse-click" => {
-                                let data: MouseClickData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+                            if let Some(duration_mins) = data.duration_mins {
+                                let ends_at = DateTime::from_millis(
+                                    DateTime::now().timestamp_millis()
+                                        + duration_mins as i64 * 60 * 1000,
+                                );
+                                if let Err(err) = Database::set_breakouts_end(
+                                    db.clone(),
+                                    &room.code,
+                                    Some(ends_at),
+                                )
+                                .await
+                                {
+                                    eprintln!("Failed to time breakouts of {}: {}", room.code, err);
+                                }
+                            }
+
+                            send_breakouts_updated(&db, &ws_state, &room.code).await;
+                        }
+
+                        "return-all" => {
+                            let data: BreakoutReturnData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            if current_user != Some(room.host_id) {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            if let Err(err) =
+                                close_breakouts(&db, &ws_state, &room, "returned-by-host").await
+                            {
+                                eprintln!("Failed to close breakouts of {}: {}", room.code, err);
+                            }
+                        }
 
-                                let response: MouseClickResponse = MouseClickResponse {
-                                    message_type: "mouse-click".to_string(),
+                        "visit-breakout" => {
+                            let data: VisitBreakoutData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
                                 };
 
-                                let response_text = serde_json::to_string(&response).unwrap();
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
+                                    Ok(Some(room)) => room,
+                                    _ => continue,
+                                };
+
+                            let host_id = match current_user {
+                                Some(id) if id == room.host_id => id,
+                                _ => {
+                                    send_permission_denied(&ws_state, &socket_id, message_type)
+                                        .await;
+                                    continue;
+                                }
+                            };
 
-                                let sender_id = {
-                                    let user_sockets = ws_state.user_sockets.lock().await;
-                                    user_sockets.get(&data.to).cloned()
+                            let breakouts =
+                                match Database::get_breakouts(db.clone(), &room.code).await {
+                                    Ok(breakouts) => breakouts,
+                                    Err(_) => continue,
                                 };
+                            let target = data.breakout.as_ref().and_then(|code| {
+                                breakouts.iter().find(|breakout| breakout.code == *code)
+                            });
+                            if data.breakout.is_some() && target.is_none() {
+                                continue;
+                            }
 
-                                if let Some(sender_id) = sender_id {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(&sender_id).cloned()
-                                    };
+                            for breakout in &breakouts {
+                                if Some(&breakout.code) != data.breakout.as_ref() {
+                                    leave_breakout_state(&ws_state, breakout, host_id).await;
+                                }
+                            }
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
+                            match target {
+                                Some(breakout) => {
+                                    enter_breakout(&ws_state, &room, breakout, host_id).await
+                                }
+                                None => {
+                                    if let Some(username) =
+                                        connected_username(&ws_state, &room.code, host_id).await
+                                    {
+                                        join_member_state(&ws_state, &room, host_id, &username)
+                                            .await;
                                     }
                                 }
                             }
+                        }
 
-                            "message" => {
-                                let data: MessageData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+                        "broadcast-breakouts" => {
+                            let data: BreakoutBroadcastData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
 
-                                let room: Room = match Database::get_room_by_code(
-                                    db.clone(),
-                                    &data.code,
-                                )
-                                .await
-                                {
+                            let room: Room =
+                                match Database::get_room_by_code(db.clone(), &data.code).await {
                                     Ok(Some(room)) => room,
                                     _ => continue,
                                 };
 
-                                let response: MessageResponse = MessageResponse {
-                                    message_type: "message".to_string(),
-                                    message: data.message,
-                                    username: data.username,
-                                    id: data.id,
+                            if current_user != Some(room.host_id) {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            // Breakout members stay participants of the parent room, so
+                            // one send reaches every group.
+                            let response = BreakoutBroadcast {
+                                message_type: "breakout-broadcast".to_string(),
+                                code: room.code.clone(),
+                                from: room.host_id,
+                                message: data.message,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_room(&ws_state, &room, &response_text).await;
+                        }
+
+                        "request-access" => {
+                            let data: RequestAccessData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
                                 };
 
+                            let is_guest = {
+                                let guests = ws_state.guests.lock().await;
+                                guests.contains_key(&data.from)
+                                    || current_user.is_some_and(|id| guests.contains_key(&id))
+                            };
+                            if is_guest {
+                                let response = GuestRestricted {
+                                    message_type: "guest-restricted".to_string(),
+                                    action: "request-access".to_string(),
+                                };
                                 let response_text = serde_json::to_string(&response).unwrap();
+                                send_to_socket(&ws_state, &socket_id, &response_text).await;
+                                continue;
+                            }
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+                            if current_user != Some(data.from)
+                                || remote_control_room(
+                                    &db,
+                                    &ws_state,
+                                    &socket_id,
+                                    &data.code,
+                                    current_user,
+                                    data.to,
+                                )
+                                .await
+                                .is_none()
+                            {
+                                continue;
+                            }
 
-                                for participant in &room.participants_id {
-                                    if let Some(sender_id) = user_sockets.get(&participant) {
-                                        let sender_arc = {
-                                            let sockets = ws_state.sockets.lock().await;
-                                            sockets.get(sender_id).cloned()
-                                        };
+                            let response: RequestAccessResponse = RequestAccessResponse {
+                                message_type: "request-access".to_string(),
+                                user_id: data.from,
+                                username: data.username,
+                            };
 
-                                        if let Some(sender_arc) = sender_arc {
-                                            let mut sender = sender_arc.lock().await;
-                                            if let Err(err) = sender
-                                                .send(Message::Text(response_text.clone().into()))
-                                                .await
-                                            {
-                                                eprintln!(
-                                                    "Failed to send message to user {}: {}",
-                                                    sender_id, err
-                                                );
-                                            }
-                                        }
+                            let response_text = serde_json::to_string(&response).unwrap();
+
+                            let user_sockets = ws_state.user_sockets.lock().await.clone();
+
+                            if let Some(sender_id) = user_sockets.get(&data.to) {
+                                let sender_arc = {
+                                    let sockets = ws_state.sockets.lock().await;
+                                    sockets.get(sender_id).cloned()
+                                };
+
+                                if let Some(sender_arc) = sender_arc {
+                                    let mut sender = sender_arc.lock().await;
+                                    if let Err(err) =
+                                        sender.send(Message::Text(response_text.into())).await
+                                    {
+                                        eprintln!(
+                                            "Failed to send message to user {}: {}",
+                                            sender_id, err
+                                        );
                                     }
                                 }
+                            }
+                        }
+
+                        "allowed-access" => {
+                            let data: AccessData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
+                                };
+
+                            let Some(room) = remote_control_room(
+                                &db,
+                                &ws_state,
+                                &socket_id,
+                                &data.code,
+                                current_user,
+                                data.user_id,
+                            )
+                            .await
+                            else {
+                                continue;
+                            };
+
+                            if !current_user
+                                .is_some_and(|id| room.can(&id, Permission::GrantRemoteControl))
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            let response: AccessResponse = AccessResponse {
+                                message_type: "allowed-access".to_string(),
+                                user_id: data.user_id,
+                                username: data.username,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            let user_sockets = ws_state.user_sockets.lock().await.clone();
 
-                                if let Some(sender_id) = user_sockets.get(&room.host_id) {
+                            for participant in &room.participants_id {
+                                if let Some(sender_id) = user_sockets.get(participant) {
                                     let sender_arc = {
                                         let sockets = ws_state.sockets.lock().await;
                                         sockets.get(sender_id).cloned()
@@ -780,8 +3525,9 @@ async fn handle_rooms(
 
                                     if let Some(sender_arc) = sender_arc {
                                         let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
+                                        if let Err(err) = sender
+                                            .send(Message::Text(response_text.clone().into()))
+                                            .await
                                         {
                                             eprintln!(
                                                 "Failed to send message to user {}: {}",
@@ -791,405 +3537,1480 @@ async fn handle_rooms(
                                     }
                                 }
                             }
-                            "screen-sharing-started" | "screen-sharing-stopped" => {
-                                let data: VideoData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+                        }
 
-                                let room: Room = match Database::get_room_by_code(
-                                    db.clone(),
-                                    &data.code,
-                                )
-                                .await
-                                {
-                                    Ok(Some(room)) => room,
-                                    _ => continue,
-                                };
-                                let response: VideoResponse = VideoResponse {
-                                    message_type: message_type.to_string(),
-                                    user_id: data.user_id,
-                                    host: data.host,
+                        "rejected-access" => {
+                            let data: AccessData =
+                                match serde_json::from_value(json["data"].clone()) {
+                                    Ok(d) => d,
+                                    Err(err) => {
+                                        eprintln!("Invalid {} payload: {}", message_type, err);
+                                        continue;
+                                    }
                                 };
 
-                                let response_text = serde_json::to_string(&response).unwrap();
+                            let Some(room) = remote_control_room(
+                                &db,
+                                &ws_state,
+                                &socket_id,
+                                &data.code,
+                                current_user,
+                                data.user_id,
+                            )
+                            .await
+                            else {
+                                continue;
+                            };
+
+                            if !current_user
+                                .is_some_and(|id| room.can(&id, Permission::GrantRemoteControl))
+                            {
+                                send_permission_denied(&ws_state, &socket_id, message_type).await;
+                                continue;
+                            }
+
+                            let response: AccessResponse = AccessResponse {
+                                message_type: "rejected-access".to_string(),
+                                user_id: data.user_id,
+                                username: data.username,
+                            };
+                            let response_text = serde_json::to_string(&response).unwrap();
+                            send_to_user(&ws_state, &data.user_id, &response_text).await;
+                        }
+                        _ => continue,
+                    }
+                }
+            }
+            _ => continue,
+        }
+    }
+
+    ws_state.sockets.lock().await.remove(&socket_id);
+    if let Some(user_id) = current_user {
+        let disconnected = {
+            let mut user_sockets = ws_state.user_sockets.lock().await;
+            if user_sockets.get(&user_id) == Some(&socket_id) {
+                user_sockets.remove(&user_id);
+                true
+            } else {
+                false
+            }
+        };
+
+        // A newer socket for the same user keeps them connected.
+        if disconnected {
+            let codes: Vec<String> = ws_state
+                .room_states
+                .lock()
+                .await
+                .iter()
+                .filter(|(_, state)| state.member(&user_id).is_some())
+                .map(|(code, _)| code.clone())
+                .collect();
+
+            for code in codes {
+                if let Ok(Some(room)) = Database::get_room_by_code(db.clone(), &code).await {
+                    update_member_state(&ws_state, &room, user_id, |member| {
+                        member.connected = false
+                    })
+                    .await;
+                    record_leave(&db, &room, user_id, "disconnected").await;
+                }
+            }
+        }
+    }
+}
+
+/// Users allowed to manage the lobby: the host and any co-hosts.
+fn room_moderators(room: &Room) -> Vec<ObjectId> {
+    std::iter::once(room.host_id)
+        .chain(
+            room.roles
+                .iter()
+                .map(|assignment| assignment.user_id)
+                .filter(|id| room.can(id, Permission::Admit)),
+        )
+        .collect()
+}
+
+/// Marks `user_id` as connected in the room's live state, sends them the full
+/// `room-state` snapshot and tells everyone else what changed.
+async fn join_member_state(ws_state: &AppState, room: &Room, user_id: ObjectId, username: &str) {
+    // With `join_muted` set, everyone who cannot mute others joins muted and
+    // has to ask before unmuting.
+    let join_muted = room.settings.join_muted && !room.can(&user_id, Permission::Mute);
+
+    let (changes, members, hand_queue) = {
+        let mut room_states = ws_state.room_states.lock().await;
+        let state = room_states.entry(room.code.clone()).or_default();
+        let mut changes = state.join(user_id, username);
+        if join_muted
+            && let Some(muted) = state.update(&user_id, |member| {
+                member.audio = false;
+                member.muted_by_host = true;
+            })
+        {
+            changes.extend(muted);
+        }
+        (changes, state.members.clone(), state.hand_queue.clone())
+    };
+
+    if join_muted {
+        let response = MuteRequested {
+            message_type: "mute-requested".to_string(),
+            code: room.code.clone(),
+            by: room.host_id,
+        };
+        let response_text = serde_json::to_string(&response).unwrap();
+        send_to_user(ws_state, &user_id, &response_text).await;
+    }
+
+    let response = RoomStateSnapshot {
+        message_type: "room-state".to_string(),
+        code: room.code.clone(),
+        host: room.host_id,
+        members,
+        hand_queue,
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_user(ws_state, &user_id, &response_text).await;
+
+    if !changes.is_empty() {
+        let others = std::iter::once(&room.host_id)
+            .chain(room.participants_id.iter())
+            .filter(|id| **id != user_id);
+        for member in others {
+            send_member_update(ws_state, &room.code, member, user_id, &changes).await;
+        }
+    }
+}
+
+/// Applies `change` to a member's live state and broadcasts a `member-updated`
+/// diff if anything changed.
+async fn update_member_state(
+    ws_state: &AppState,
+    room: &Room,
+    user_id: ObjectId,
+    change: impl FnOnce(&mut MemberState),
+) {
+    let changes = {
+        let mut room_states = ws_state.room_states.lock().await;
+        room_states
+            .get_mut(&room.code)
+            .and_then(|state| state.update(&user_id, change))
+    };
+
+    if let Some(changes) = changes {
+        let response = MemberUpdated {
+            message_type: "member-updated".to_string(),
+            code: room.code.clone(),
+            user_id,
+            changes,
+        };
+        let response_text = serde_json::to_string(&response).unwrap();
+        send_to_room(ws_state, room, &response_text).await;
+    }
+}
+
+/// Raises or lowers a member's hand, broadcasting the change and the updated
+/// queue.
+async fn set_hand(ws_state: &AppState, room: &Room, user_id: ObjectId, raised: bool) {
+    let (changes, queue) = {
+        let mut room_states = ws_state.room_states.lock().await;
+        let Some(state) = room_states.get_mut(&room.code) else {
+            return;
+        };
+        let changes = if raised {
+            state.raise_hand(&user_id)
+        } else {
+            state.lower_hand(&user_id)
+        };
+        (changes, state.hand_queue.clone())
+    };
+
+    if let Some(changes) = changes {
+        let response = MemberUpdated {
+            message_type: "member-updated".to_string(),
+            code: room.code.clone(),
+            user_id,
+            changes,
+        };
+        let response_text = serde_json::to_string(&response).unwrap();
+        send_to_room(ws_state, room, &response_text).await;
+    }
+
+    let response = HandQueue {
+        message_type: "hand-queue".to_string(),
+        code: room.code.clone(),
+        queue,
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_room(ws_state, room, &response_text).await;
+}
+
+async fn send_member_update(
+    ws_state: &AppState,
+    code: &str,
+    to: &ObjectId,
+    user_id: ObjectId,
+    changes: &serde_json::Map<String, serde_json::Value>,
+) {
+    let response = MemberUpdated {
+        message_type: "member-updated".to_string(),
+        code: code.to_string(),
+        user_id,
+        changes: changes.clone(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_user(ws_state, to, &response_text).await;
+}
+
+async fn remove_member_state(ws_state: &AppState, room_code: &str, user_id: &ObjectId) {
+    if let Some(state) = ws_state.room_states.lock().await.get_mut(room_code) {
+        state.remove(user_id);
+    }
+}
+
+async fn send_typing(ws_state: &AppState, room: &Room, user_id: ObjectId, typing: bool) {
+    let response = TypingResponse {
+        message_type: "typing".to_string(),
+        code: room.code.clone(),
+        user_id,
+        typing,
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_room(ws_state, room, &response_text).await;
+}
+
+async fn send_message_rejected(ws_state: &AppState, socket_id: &Uuid, code: &str, reason: &str) {
+    let response = MessageRejected {
+        message_type: "message-rejected".to_string(),
+        code: code.to_string(),
+        reason: reason.to_string(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_socket(ws_state, socket_id, &response_text).await;
+}
+
+async fn send_permission_denied(ws_state: &AppState, socket_id: &Uuid, action: &str) {
+    let response = PermissionDenied {
+        message_type: "permission-denied".to_string(),
+        action: action.to_string(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_socket(ws_state, socket_id, &response_text).await;
+}
+
+/// Sends the full lobby to the room's moderators and each waiting user their
+/// place in the queue.
+async fn notify_lobby(ws_state: &AppState, room: &Room) {
+    let response = LobbyUpdated {
+        message_type: "lobby-updated".to_string(),
+        code: room.code.clone(),
+        lobby: room.lobby.clone(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    for moderator in room_moderators(room) {
+        send_to_user(ws_state, &moderator, &response_text).await;
+    }
+
+    for (index, entry) in room.lobby.iter().enumerate() {
+        let response = LobbyPosition {
+            message_type: "lobby-position".to_string(),
+            code: room.code.clone(),
+            position: index + 1,
+            total: room.lobby.len(),
+        };
+        let response_text = serde_json::to_string(&response).unwrap();
+        send_to_user(ws_state, &entry.user_id, &response_text).await;
+    }
+}
+
+/// Periodically drops join requests that have waited longer than
+/// `LOBBY_REQUEST_TTL_SECS` (10 minutes by default).
+pub async fn expire_lobby_requests(db: Arc<Database>, ws_state: Arc<AppState>) {
+    let ttl_secs: i64 = env::var("LOBBY_REQUEST_TTL_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(600);
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+
+    loop {
+        interval.tick().await;
+
+        let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - ttl_secs * 1000);
+        let rooms = match Database::expire_lobby_entries(db.clone(), cutoff).await {
+            Ok(rooms) => rooms,
+            Err(err) => {
+                eprintln!("Failed to expire lobby requests: {}", err);
+                continue;
+            }
+        };
+
+        for room in rooms {
+            let response = LobbyExpired {
+                message_type: "lobby-expired".to_string(),
+                code: room.code.clone(),
+            };
+            let response_text = serde_json::to_string(&response).unwrap();
+            for entry in room
+                .lobby
+                .iter()
+                .filter(|entry| entry.requested_at < cutoff)
+            {
+                send_to_user(&ws_state, &entry.user_id, &response_text).await;
+            }
+
+            if let Ok(Some(room)) = Database::get_room_by_code(db.clone(), &room.code).await {
+                notify_lobby(&ws_state, &room).await;
+            }
+        }
+    }
+}
+
+/// Warns the participants of scheduled meetings `MEETING_END_WARNING_MINS`
+/// (5 by default) before the end, then ends the room at the scheduled time.
+pub async fn end_scheduled_meetings(db: Arc<Database>, ws_state: Arc<AppState>) {
+    let warning_mins: i64 = env::var("MEETING_END_WARNING_MINS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(5);
+    let mut interval = tokio::time::interval(Duration::from_secs(15));
+
+    loop {
+        interval.tick().await;
+
+        let now = DateTime::now();
+        let cutoff = DateTime::from_millis(now.timestamp_millis() + warning_mins * 60 * 1000);
+        let meetings = match Database::get_meetings_to_warn(db.clone(), cutoff).await {
+            Ok(meetings) => meetings,
+            Err(err) => {
+                eprintln!("Failed to load meetings ending soon: {}", err);
+                continue;
+            }
+        };
+        for meeting in meetings {
+            let Some(meeting_id) = meeting._id else {
+                continue;
+            };
+            if meeting.end_time > now
+                && let Ok(Some(room)) =
+                    Database::get_room_by_code(db.clone(), &meeting.room_code).await
+            {
+                let response = MeetingEndingSoon {
+                    message_type: "meeting-ending-soon".to_string(),
+                    code: room.code.clone(),
+                    ends_at: meeting.end_time.try_to_rfc3339_string().unwrap_or_default(),
+                    seconds_left: (meeting.end_time.timestamp_millis() - now.timestamp_millis())
+                        / 1000,
+                    reason: "meeting-ended".to_string(),
+                };
+                let response_text = serde_json::to_string(&response).unwrap();
+                send_to_room(&ws_state, &room, &response_text).await;
+            }
+            if let Err(err) = Database::mark_meeting_warned(db.clone(), meeting_id).await {
+                eprintln!("Failed to record meeting warning: {}", err);
+            }
+        }
+
+        let meetings = match Database::get_meetings_to_end(db.clone(), now).await {
+            Ok(meetings) => meetings,
+            Err(err) => {
+                eprintln!("Failed to load finished meetings: {}", err);
+                continue;
+            }
+        };
+        for meeting in meetings {
+            if let Ok(Some(room)) = Database::get_room_by_code(db.clone(), &meeting.room_code).await
+            {
+                finish_meeting(&db, &ws_state, &meeting, &room, "meeting-ended").await;
+            }
+        }
+    }
+}
+
+/// Marks a scheduled meeting ended and closes its room, keeping the code when
+/// the meeting's series has further occurrences.
+async fn finish_meeting(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    meeting: &Meeting,
+    room: &Room,
+    reason: &str,
+) {
+    let Some(meeting_id) = meeting._id else {
+        return;
+    };
+    if let Err(err) = Database::mark_meeting_ended(db.clone(), meeting_id).await {
+        eprintln!("Failed to mark meeting ended: {}", err);
+        return;
+    }
+
+    // Top up the series first so its room survives for the next one.
+    if let Some(series_id) = meeting.series_id
+        && let Ok(Some(series)) = Database::get_series_by_id(db.clone(), series_id).await
+        && let Err(err) = schedule_occurrences(db.clone(), &series).await
+    {
+        eprintln!(
+            "Failed to schedule occurrences of series {}: {}",
+            series_id, err
+        );
+    }
+
+    if let Err(err) = close_room(db.clone(), ws_state, room, reason).await {
+        eprintln!("Failed to end meeting room {}: {}", room.code, err);
+    }
+}
+
+/// Ends sessions that run past the `max_meeting_mins` of the host's plan,
+/// warning the room `MEETING_END_WARNING_MINS` (5 by default) beforehand.
+pub async fn enforce_duration_limits(db: Arc<Database>, ws_state: Arc<AppState>) {
+    let warning_mins: i64 = env::var("MEETING_END_WARNING_MINS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(5);
+    // Session start of each room already warned, so a new session warns again.
+    let mut warned: HashMap<String, i64> = HashMap::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(15));
+
+    loop {
+        interval.tick().await;
+
+        let active: Vec<String> = ws_state
+            .room_states
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, state)| state.members.iter().any(|member| member.connected))
+            .map(|(code, _)| code.clone())
+            .collect();
+        warned.retain(|code, _| active.contains(code));
+
+        for code in active {
+            let Ok(Some(room)) = Database::get_room_by_code(db.clone(), &code).await else {
+                continue;
+            };
+            let Some(max_mins) = host_plan(&db, &ws_state, &room).await.max_meeting_mins else {
+                continue;
+            };
+            let started_at = match session_started_at(&db, &room).await {
+                Ok(Some(started_at)) => started_at.timestamp_millis(),
+                Ok(None) => continue,
+                Err(err) => {
+                    eprintln!("Failed to load the session of room {}: {}", code, err);
+                    continue;
+                }
+            };
+
+            let now = DateTime::now().timestamp_millis();
+            let ends_at = started_at + max_mins as i64 * 60 * 1000;
+            if now >= ends_at {
+                warned.remove(&code);
+                let meeting = Database::get_meeting_by_code(db.clone(), &code)
+                    .await
+                    .ok()
+                    .flatten()
+                    .filter(|meeting| meeting.start_time.timestamp_millis() <= now);
+                match meeting {
+                    Some(meeting) => {
+                        finish_meeting(&db, &ws_state, &meeting, &room, "duration-limit").await
+                    }
+                    None => {
+                        if let Err(err) =
+                            close_room(db.clone(), &ws_state, &room, "duration-limit").await
+                        {
+                            eprintln!("Failed to end room {}: {}", code, err);
+                        }
+                    }
+                }
+            } else if now >= ends_at - warning_mins * 60 * 1000
+                && warned.get(&code) != Some(&started_at)
+            {
+                let response = MeetingEndingSoon {
+                    message_type: "meeting-ending-soon".to_string(),
+                    code: code.clone(),
+                    ends_at: DateTime::from_millis(ends_at)
+                        .try_to_rfc3339_string()
+                        .unwrap_or_default(),
+                    seconds_left: (ends_at - now) / 1000,
+                    reason: "duration-limit".to_string(),
+                };
+                let response_text = serde_json::to_string(&response).unwrap();
+                send_to_room(&ws_state, &room, &response_text).await;
+                warned.insert(code, started_at);
+            }
+        }
+    }
+}
+
+/// The plan of the room's host, or the default plan when the host can't be
+/// loaded.
+async fn host_plan(db: &Arc<Database>, ws_state: &AppState, room: &Room) -> Plan {
+    match Database::get_user_by_id(db.clone(), room.host_id).await {
+        Ok(Some(host)) => ws_state.plans.plan_for(&host).clone(),
+        _ => ws_state.plans.default_plan().clone(),
+    }
+}
+
+/// Remote control needs both the room setting and a host plan that includes it.
+async fn remote_control_enabled(db: &Arc<Database>, ws_state: &AppState, room: &Room) -> bool {
+    room.settings.remote_control_allowed && host_plan(db, ws_state, room).await.remote_control
+}
+
+async fn send_remote_control_disabled(ws_state: &AppState, socket_id: &Uuid) {
+    let response = FeatureDisabled {
+        message_type: "feature-disabled".to_string(),
+        feature: "remote-control".to_string(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_socket(ws_state, socket_id, &response_text).await;
+}
+
+/// The room remote-control traffic between `from` and `to` may be relayed in:
+/// both have to be in it and remote control has to be enabled there.
+async fn remote_control_room(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    socket_id: &Uuid,
+    code: &str,
+    from: Option<ObjectId>,
+    to: ObjectId,
+) -> Option<Room> {
+    let room = Database::get_room_by_code(db.clone(), code).await.ok()??;
+
+    let in_room = |id: &ObjectId| *id == room.host_id || room.participants_id.contains(id);
+    if !from.is_some_and(|id| in_room(&id)) || !in_room(&to) {
+        return None;
+    }
+
+    if !remote_control_enabled(db, ws_state, &room).await {
+        send_remote_control_disabled(ws_state, socket_id).await;
+        return None;
+    }
+
+    Some(room)
+}
+
+async fn send_admit_failed(ws_state: &AppState, socket_id: &Uuid, user_id: ObjectId, reason: &str) {
+    let response = AdmitFailed {
+        message_type: "admit-failed".to_string(),
+        user_id,
+        reason: reason.to_string(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_socket(ws_state, socket_id, &response_text).await;
+}
+
+async fn send_join_denied(ws_state: &AppState, socket_id: &Uuid, reason: &str) {
+    let response = JoinDenied {
+        message_type: "join-denied".to_string(),
+        reason: reason.to_string(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_socket(ws_state, socket_id, &response_text).await;
+}
+
+/// Ends rooms nobody has been connected to for `ROOM_IDLE_TIMEOUT_MINS` (15 by
+/// default), freeing their codes. Rooms held for a scheduled meeting are left
+/// to `end_scheduled_meetings`.
+pub async fn sweep_idle_rooms(db: Arc<Database>, ws_state: Arc<AppState>) {
+    let timeout_mins: i64 = env::var("ROOM_IDLE_TIMEOUT_MINS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(15);
+    let mut idle_since: HashMap<String, i64> = HashMap::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+
+    loop {
+        interval.tick().await;
+
+        let rooms = match Database::get_all_rooms(db.clone()).await {
+            Ok(rooms) => rooms,
+            Err(err) => {
+                eprintln!("Failed to load rooms for the idle sweep: {}", err);
+                continue;
+            }
+        };
+        let active: Vec<String> = ws_state
+            .room_states
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, state)| state.members.iter().any(|member| member.connected))
+            .map(|(code, _)| code.clone())
+            .collect();
+
+        let now = DateTime::now().timestamp_millis();
+        idle_since.retain(|code, _| rooms.iter().any(|room| room.code == *code));
+
+        for room in rooms {
+            if active.contains(&room.code) || room.parent_code.is_some() {
+                idle_since.remove(&room.code);
+                continue;
+            }
+
+            let since = *idle_since.entry(room.code.clone()).or_insert(now);
+            if now - since < timeout_mins * 60 * 1000 {
+                continue;
+            }
+            if let Ok(Some(_)) = Database::get_meeting_by_code(db.clone(), &room.code).await {
+                continue;
+            }
+
+            idle_since.remove(&room.code);
+            if let Err(err) = end_room(db.clone(), &ws_state, &room, "idle").await {
+                eprintln!("Failed to end idle room {}: {}", room.code, err);
+            }
+        }
+    }
+}
+
+/// Tells rooms that members who went quiet without `typing-stop` are no longer
+/// typing.
+pub async fn expire_typing(db: Arc<Database>, ws_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        interval.tick().await;
+
+        let expired: Vec<(String, Vec<ObjectId>)> = ws_state
+            .room_states
+            .lock()
+            .await
+            .iter_mut()
+            .map(|(code, state)| (code.clone(), state.expire_typing(TYPING_TIMEOUT)))
+            .filter(|(_, expired)| !expired.is_empty())
+            .collect();
+
+        for (code, user_ids) in expired {
+            let Ok(Some(room)) = Database::get_room_by_code(db.clone(), &code).await else {
+                continue;
+            };
+            for user_id in user_ids {
+                send_typing(&ws_state, &room, user_id, false).await;
+            }
+        }
+    }
+}
+
+/// Returns everyone to the main room once the timer set by `assign-breakouts`
+/// runs out.
+pub async fn end_timed_breakouts(db: Arc<Database>, ws_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(15));
+
+    loop {
+        interval.tick().await;
+
+        let rooms = match Database::get_rooms_with_breakouts_due(db.clone(), DateTime::now()).await
+        {
+            Ok(rooms) => rooms,
+            Err(err) => {
+                eprintln!("Failed to load timed breakouts: {}", err);
+                continue;
+            }
+        };
+        for room in rooms {
+            if let Err(err) = close_breakouts(&db, &ws_state, &room, "time-up").await {
+                eprintln!("Failed to close breakouts of {}: {}", room.code, err);
+            }
+        }
+    }
+}
+
+/// Deletes a room's breakouts and tells everyone to reconnect in the main
+/// room.
+async fn close_breakouts(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    parent: &Room,
+    reason: &str,
+) -> mongodb::error::Result<()> {
+    let breakouts = Database::get_breakouts(db.clone(), &parent.code).await?;
+    if breakouts.is_empty() && parent.breakouts_end_at.is_none() {
+        return Ok(());
+    }
+
+    Database::delete_breakouts(db.clone(), &parent.code).await?;
+    Database::set_breakouts_end(db.clone(), &parent.code, None).await?;
+    {
+        let mut room_states = ws_state.room_states.lock().await;
+        for breakout in &breakouts {
+            room_states.remove(&breakout.code);
+        }
+    }
+
+    let response = BreakoutsClosed {
+        message_type: "breakouts-closed".to_string(),
+        code: parent.code.clone(),
+        reason: reason.to_string(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_room(ws_state, parent, &response_text).await;
+    Ok(())
+}
+
+/// Assigns a parent-room member to one of its breakouts, moving them out of
+/// the group they were in.
+async fn move_to_breakout(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    parent: &Room,
+    breakouts: &[Room],
+    user_id: ObjectId,
+    breakout_code: &str,
+) -> mongodb::error::Result<()> {
+    Database::assign_to_breakout(db.clone(), &parent.code, breakout_code, user_id).await?;
+    for breakout in breakouts
+        .iter()
+        .filter(|breakout| breakout.code != breakout_code)
+    {
+        leave_breakout_state(ws_state, breakout, user_id).await;
+    }
+
+    if let Some(breakout) = Database::get_room_by_code(db.clone(), breakout_code).await? {
+        enter_breakout(ws_state, parent, &breakout, user_id).await;
+    }
+    Ok(())
+}
+
+/// Takes a departing member out of any breakout of `parent`.
+async fn drop_from_breakouts(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    parent: &Room,
+    user_id: ObjectId,
+) {
+    let Ok(breakouts) = Database::get_breakouts(db.clone(), &parent.code).await else {
+        return;
+    };
+    if breakouts.is_empty() {
+        return;
+    }
+
+    if let Err(err) = Database::remove_from_breakouts(db.clone(), &parent.code, user_id).await {
+        eprintln!("Failed to remove {} from breakouts: {}", user_id, err);
+    }
+    for breakout in &breakouts {
+        leave_breakout_state(ws_state, breakout, user_id).await;
+    }
+    send_breakouts_updated(db, ws_state, &parent.code).await;
+}
+
+/// Puts a connected member into a breakout's live state and sends them
+/// `moved-to-breakout`, so they only negotiate with that group. Members who
+/// are offline pick their group up when they are next admitted.
+async fn enter_breakout(ws_state: &AppState, parent: &Room, breakout: &Room, user_id: ObjectId) {
+    let Some(username) = connected_username(ws_state, &parent.code, user_id).await else {
+        return;
+    };
+
+    let response = MovedToBreakout {
+        message_type: "moved-to-breakout".to_string(),
+        code: breakout.code.clone(),
+        parent_code: parent.code.clone(),
+        name: breakout.breakout_name.clone().unwrap_or_default(),
+        members: breakout.participants_id.clone(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_user(ws_state, &user_id, &response_text).await;
+
+    join_member_state(ws_state, breakout, user_id, &username).await;
+}
+
+async fn leave_breakout_state(ws_state: &AppState, breakout: &Room, user_id: ObjectId) {
+    let was_member = ws_state
+        .room_states
+        .lock()
+        .await
+        .get_mut(&breakout.code)
+        .is_some_and(|state| {
+            let present = state.member(&user_id).is_some();
+            state.remove(&user_id);
+            present
+        });
+
+    if was_member {
+        let response = ParticipantLeft {
+            message_type: "participant-left".to_string(),
+            user: user_id,
+        };
+        let response_text = serde_json::to_string(&response).unwrap();
+        send_to_room(ws_state, breakout, &response_text).await;
+    }
+}
+
+async fn connected_username(
+    ws_state: &AppState,
+    room_code: &str,
+    user_id: ObjectId,
+) -> Option<String> {
+    ws_state
+        .room_states
+        .lock()
+        .await
+        .get(room_code)
+        .and_then(|state| state.member(&user_id))
+        .filter(|member| member.connected)
+        .map(|member| member.username.clone())
+}
+
+async fn send_breakouts_updated(db: &Arc<Database>, ws_state: &AppState, parent_code: &str) {
+    let Ok(Some(parent)) = Database::get_room_by_code(db.clone(), parent_code).await else {
+        return;
+    };
+    let Ok(breakouts) = Database::get_breakouts(db.clone(), parent_code).await else {
+        return;
+    };
+
+    let response = BreakoutsUpdated {
+        message_type: "breakouts-updated".to_string(),
+        code: parent.code.clone(),
+        breakouts: breakouts
+            .into_iter()
+            .map(|breakout| BreakoutView {
+                code: breakout.code,
+                name: breakout.breakout_name.unwrap_or_default(),
+                members: breakout.participants_id,
+            })
+            .collect(),
+        ends_at: parent
+            .breakouts_end_at
+            .and_then(|ends_at| ends_at.try_to_rfc3339_string().ok()),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_room(ws_state, &parent, &response_text).await;
+}
+
+/// Checks the room settings that apply to every non-host joiner, returning the
+/// reason the join is refused.
+fn join_denial_reason(
+    room: &Room,
+    plan: &Plan,
+    user_id: ObjectId,
+    password: Option<&str>,
+) -> Option<&'static str> {
+    let settings = &room.settings;
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+    if let Some(reason) = admit_denial_reason(room, plan, user_id) {
+        return Some(reason);
+    }
 
-                                for participant in &room.participants_id {
-                                    if let Some(sender_id) = user_sockets.get(&participant) {
-                                        let sender_arc = {
-                                            let sockets = ws_state.sockets.lock().await;
-                                            sockets.get(sender_id).cloned()
-                                        };
+    if settings.locked {
+        return Some("locked");
+    }
 
-                                        if let Some(sender_arc) = sender_arc {
-                                            let mut sender = sender_arc.lock().await;
-                                            if let Err(err) = sender
-                                                .send(Message::Text(response_text.clone().into()))
-                                                .await
-                                            {
-                                                eprintln!(
-                                                    "Failed to send message to user {}: {}",
-                                                    sender_id, err
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
+    if let Some(password_hash) = &settings.password_hash {
+        let valid = password
+            .is_some_and(|password| verify_password(password, password_hash).unwrap_or(false));
+        if !valid {
+            return Some("invalid-password");
+        }
+    }
 
-                                if let Some(sender_id) = user_sockets.get(&room.host_id) {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(sender_id).cloned()
-                                    };
+    None
+}
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
-                                    }
-                                }
-                            }
+/// Why someone already waiting in the lobby, or holding a pre-approved invite,
+/// can't be let in. The lock and password don't apply to them: lobby entries
+/// were checked when they joined, and the invite stands in for both.
+fn admit_denial_reason(room: &Room, plan: &Plan, user_id: ObjectId) -> Option<&'static str> {
+    let settings = &room.settings;
 
-                            "video-started" | "video-stopped" => {
-                                let data: VideoData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+    if room.banned.iter().any(|entry| entry.user_id == user_id) {
+        return Some("banned");
+    }
 
-                                let room: Room = match Database::get_room_by_code(
-                                    db.clone(),
-                                    &data.code,
-                                )
-                                .await
-                                {
-                                    Ok(Some(room)) => room,
-                                    _ => continue,
-                                };
-                                let response: VideoResponse = VideoResponse {
-                                    message_type: message_type.to_string(),
-                                    user_id: data.user_id,
-                                    host: data.host,
-                                };
+    let max_participants = match (settings.max_participants, plan.max_participants) {
+        (Some(room_cap), Some(plan_cap)) => Some(room_cap.min(plan_cap)),
+        (room_cap, plan_cap) => room_cap.or(plan_cap),
+    };
+    if let Some(max_participants) = max_participants
+        && !room.participants_id.contains(&user_id)
+        && room.participants_id.len() + 1 >= max_participants as usize
+    {
+        return Some("room-full");
+    }
 
-                                let response_text = serde_json::to_string(&response).unwrap();
+    None
+}
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+fn email_domain(email: &str) -> Option<&str> {
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .filter(|domain| !domain.is_empty())
+}
 
-                                for participant in &room.participants_id {
-                                    if let Some(sender_id) = user_sockets.get(&participant) {
-                                        let sender_arc = {
-                                            let sockets = ws_state.sockets.lock().await;
-                                            sockets.get(sender_id).cloned()
-                                        };
+/// Adds `user_id` to the room and introduces them to everyone in it. The
+/// participant list comes from the stored room rather than the host's UI.
+async fn admit_to_room(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    room: &Room,
+    user_id: ObjectId,
+    username: &str,
+    device: Option<String>,
+) -> mongodb::error::Result<()> {
+    Database::add_participant_to_room(db.clone(), &room.code, user_id).await?;
+    record_join(db, ws_state, room, user_id, username, false, device).await;
+
+    let mut member_ids = room.participants_id.clone();
+    member_ids.push(room.host_id);
+    let usernames = Database::get_usernames(db.clone(), &member_ids).await?;
+    let guests = ws_state.guests.lock().await.clone();
+    let username_of = |id: &ObjectId| -> String {
+        usernames
+            .get(id)
+            .or_else(|| guests.get(id))
+            .cloned()
+            .unwrap_or_else(|| "Guest".to_string())
+    };
+
+    let state = ws_state
+        .room_states
+        .lock()
+        .await
+        .get(&room.code)
+        .cloned()
+        .unwrap_or_default();
+
+    let participants: Vec<Participant> = room
+        .participants_id
+        .iter()
+        .filter(|id| **id != user_id)
+        .map(|id| Participant {
+            username: username_of(id),
+            id: *id,
+            video: state.member(id).is_some_and(|member| member.video),
+        })
+        .collect();
+    let host_state = state.member(&room.host_id);
+    let host = Host {
+        username: username_of(&room.host_id),
+        id: room.host_id,
+        video: host_state.is_some_and(|member| member.video),
+        screen: host_state.is_some_and(|member| member.screen),
+    };
+
+    for member in participants
+        .iter()
+        .map(|p| p.id)
+        .chain(std::iter::once(host.id))
+    {
+        let response = RequestAcceptedResponseTOParticipants {
+            message_type: "new-participant".to_string(),
+            user_id,
+            username: username.to_string(),
+            participant: member,
+            host: host.clone(),
+        };
+        let response_text = serde_json::to_string(&response).unwrap();
+        send_to_user(ws_state, &member, &response_text).await;
+    }
 
-                                        if let Some(sender_arc) = sender_arc {
-                                            let mut sender = sender_arc.lock().await;
-                                            if let Err(err) = sender
-                                                .send(Message::Text(response_text.clone().into()))
-                                                .await
-                                            {
-                                                eprintln!(
-                                                    "Failed to send message to user {}: {}",
-                                                    sender_id, err
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
+    let response = RequestAcceptedResponse {
+        message_type: "participant-joined".to_string(),
+        user_id,
+        username: username.to_string(),
+        participants,
+        host,
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_user(ws_state, &user_id, &response_text).await;
+
+    if let Some(room) = Database::get_room_by_code(db.clone(), &room.code).await? {
+        join_member_state(ws_state, &room, user_id, username).await;
+    }
+    send_chat_history(db, ws_state, room, user_id).await;
 
-                                if let Some(sender_id) = user_sockets.get(&room.host_id) {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(sender_id).cloned()
-                                    };
+    // Someone reconnecting during breakouts goes straight back to their group.
+    if let Some(breakout) =
+        Database::get_breakout_for_member(db.clone(), &room.code, user_id).await?
+    {
+        enter_breakout(ws_state, room, &breakout, user_id).await;
+    }
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
-                                    }
-                                }
-                            }
+    Ok(())
+}
 
-                            "leave-room" => {
-                                let data: LeaveRoomData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+/// Sends a new member the last `CHAT_HISTORY_ON_JOIN` (50 by default) messages
+/// of the room and of their own private chats, oldest first, with everyone's
+/// read markers, those of the people they chat with privately, and their unread
+/// count. Older messages are paged in from
+/// `/room/{code}/messages` and `/room/{code}/private-messages`.
+async fn send_chat_history(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    room: &Room,
+    user_id: ObjectId,
+) {
+    let limit: i64 = env::var("CHAT_HISTORY_ON_JOIN")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(50);
+
+    let public = Database::get_messages(db.clone(), &room.code, room.created_at, None, limit + 1);
+    let private = Database::get_private_messages(
+        db.clone(),
+        &room.code,
+        user_id,
+        room.created_at,
+        None,
+        limit + 1,
+    );
+    let markers = Database::get_read_markers(db.clone(), &room.code, room.created_at);
+    let (mut messages, mut private_messages, read_markers) =
+        match tokio::try_join!(public, private, markers) {
+            Ok(history) => history,
+            Err(err) => {
+                eprintln!("Failed to load chat history of {}: {}", room.code, err);
+                return;
+            }
+        };
+    let read_up_to = read_markers
+        .iter()
+        .find(|marker| marker.user_id == user_id)
+        .map(|marker| marker.message_id);
+    let unread_count =
+        Database::count_unread(db.clone(), &room.code, user_id, room.created_at, read_up_to)
+            .await
+            .unwrap_or_default();
+
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit.max(0) as usize);
+    messages.reverse();
+    let has_more_private = private_messages.len() as i64 > limit;
+    private_messages.truncate(limit.max(0) as usize);
+    private_messages.reverse();
+
+    // Only the people this member has private chats with, themselves included.
+    let mut correspondents: Vec<ObjectId> = private_messages
+        .iter()
+        .flat_map(|message| message.recipients.iter().chain([&message.sender_id]))
+        .copied()
+        .chain([user_id])
+        .collect();
+    correspondents.sort();
+    correspondents.dedup();
+    let private_read_markers = Database::get_private_read_markers(
+        db.clone(),
+        &room.code,
+        room.created_at,
+        &correspondents,
+    )
+    .await
+    .unwrap_or_default();
+
+    let response = ChatHistory {
+        message_type: "chat-history".to_string(),
+        code: room.code.clone(),
+        messages: messages.iter().map(MessageResponse::new).collect(),
+        has_more,
+        private_messages: private_messages.iter().map(MessageResponse::new).collect(),
+        has_more_private,
+        read_markers: read_markers.iter().map(ReadMarkerView::new).collect(),
+        private_read_markers: private_read_markers
+            .iter()
+            .map(ReadMarkerView::new)
+            .collect(),
+        unread_count,
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_user(ws_state, &user_id, &response_text).await;
+}
 
-                                let room: Room = match Database::get_room_by_code(
-                                    db.clone(),
-                                    &data.code,
-                                )
-                                .await
-                                {
-                                    Ok(Some(room)) => room,
-                                    _ => continue,
-                                };
+/// Opens an attendance session. Failures are logged rather than blocking the
+/// join.
+async fn record_join(
+    db: &Arc<Database>,
+    ws_state: &AppState,
+    room: &Room,
+    user_id: ObjectId,
+    username: &str,
+    host: bool,
+    device: Option<String>,
+) {
+    let session = AttendanceRecord {
+        _id: None,
+        user_id,
+        room_code: room.code.clone(),
+        username: username.to_string(),
+        guest: ws_state.guests.lock().await.contains_key(&user_id),
+        host,
+        joined_at: None,
+        left_at: None,
+        device,
+        leave_reason: None,
+    };
+    let result = match session_boundary(db, room).await {
+        Ok(since) => Database::record_join(db.clone(), session, since).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to record join for {}: {}", user_id, err);
+    }
+}
 
-                                let response_text: String;
+async fn record_leave(db: &Arc<Database>, room: &Room, user_id: ObjectId, reason: &str) {
+    let result = match session_boundary(db, room).await {
+        Ok(since) => Database::record_leave(db.clone(), &room.code, user_id, since, reason).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to record leave for {}: {}", user_id, err);
+    }
+}
 
-                                if data.user_id == room.host_id {
-                                    if room.participants_id.len() == 0 {
-                                        match Database::delete_room(db.clone(), &data.code).await {
-                                            Ok(_) => println!("Room deleted"),
-                                            Err(_) => continue,
-                                        };
-                                        continue;
-                                    }
-                                    let user_id = room.participants_id[0];
-                                    match Database::remove_participant_from_room(
-                                        db.clone(),
-                                        &data.code,
-                                        user_id,
-                                    )
-                                    .await
-                                    {
-                                        Ok(_) => println!("Participant removed"),
-                                        Err(_) => continue,
-                                    };
-                                    match Database::update_host_id(db.clone(), &data.code, user_id)
-                                        .await
-                                    {
-                                        Ok(_) => println!("Host changed"),
-                                        Err(_) => continue,
-                                    };
-                                    let user: User =
-                                        match Database::get_user_by_id(db.clone(), user_id).await {
-                                            Ok(Some(user)) => user,
-                                            _ => continue,
-                                        };
-                                    let response = HostLeftresponse {
-                                        message_type: "host-left".to_string(),
-                                        host: user_id,
-                                        username: user.username,
-                                    };
+async fn send_to_socket(ws_state: &AppState, socket_id: &Uuid, text: &str) {
+    let sender_arc = {
+        let sockets = ws_state.sockets.lock().await;
+        sockets.get(socket_id).cloned()
+    };
 
-                                    response_text = serde_json::to_string(&response).unwrap();
-                                } else {
-                                    match Database::remove_participant_from_room(
-                                        db.clone(),
-                                        &data.code,
-                                        data.user_id,
-                                    )
-                                    .await
-                                    {
-                                        Ok(_) => println!("Participant removed"),
-                                        Err(_) => continue,
-                                    };
+    if let Some(sender_arc) = sender_arc {
+        let mut sender = sender_arc.lock().await;
+        if let Err(err) = sender.send(Message::Text(text.into())).await {
+            eprintln!("Failed to send message to user {}: {}", socket_id, err);
+        }
+    }
+}
 
-                                    let response = ParticipantLeft {
-                                        message_type: "participant-left".to_string(),
-                                        user: data.user_id,
-                                    };
+async fn send_to_user(ws_state: &AppState, user_id: &ObjectId, text: &str) {
+    let sender_id = {
+        let user_sockets = ws_state.user_sockets.lock().await;
+        user_sockets.get(user_id).cloned()
+    };
 
-                                    response_text = serde_json::to_string(&response).unwrap();
-                                }
+    if let Some(sender_id) = sender_id {
+        send_to_socket(ws_state, &sender_id, text).await;
+    }
+}
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+/// Deletes a room and tells everyone in it why it ended.
+pub async fn end_room(
+    db: Arc<Database>,
+    ws_state: &AppState,
+    room: &Room,
+    reason: &str,
+) -> mongodb::error::Result<()> {
+    close_breakouts(&db, ws_state, room, reason).await?;
+    let since = session_boundary(&db, room).await?;
+    Database::delete_room(db.clone(), &room.code).await?;
+    Database::close_attendance(db.clone(), &room.code, since, reason).await?;
+    archive_session(&db, room, reason).await;
+    send_room_ended(ws_state, room, reason).await;
+    Ok(())
+}
 
-                                for participant in &room.participants_id {
-                                    if let Some(sender_id) = user_sockets.get(&participant) {
-                                        let sender_arc = {
-                                            let sockets = ws_state.sockets.lock().await;
-                                            sockets.get(sender_id).cloned()
-                                        };
+/// Ends the current session in a room. A room still reserved for an upcoming
+/// meeting is emptied and keeps its code; any other room is deleted.
+pub async fn close_room(
+    db: Arc<Database>,
+    ws_state: &AppState,
+    room: &Room,
+    reason: &str,
+) -> mongodb::error::Result<()> {
+    if Database::get_meeting_by_code(db.clone(), &room.code)
+        .await?
+        .is_none()
+    {
+        return end_room(db, ws_state, room, reason).await;
+    }
 
-                                        if let Some(sender_arc) = sender_arc {
-                                            let mut sender = sender_arc.lock().await;
-                                            if let Err(err) = sender
-                                                .send(Message::Text(response_text.clone().into()))
-                                                .await
-                                            {
-                                                eprintln!(
-                                                    "Failed to send message to user {}: {}",
-                                                    sender_id, err
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
+    close_breakouts(&db, ws_state, room, reason).await?;
+    let since = session_boundary(&db, room).await?;
+    Database::reset_room(db.clone(), &room.code).await?;
+    Database::close_attendance(db.clone(), &room.code, since, reason).await?;
+    archive_session(&db, room, reason).await;
+    send_room_ended(ws_state, room, reason).await;
+    Ok(())
+}
 
-                                if let Some(sender_id) = user_sockets.get(&room.host_id) {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(sender_id).cloned()
-                                    };
+/// Stores a summary of the session ending in `room` once its attendance has
+/// been closed, logging rather than failing the end of the room.
+async fn archive_session(db: &Arc<Database>, room: &Room, reason: &str) {
+    let result = match session_history(db, room, reason).await {
+        Ok(Some(history)) => Database::archive_session(db.clone(), history).await,
+        Ok(None) => Ok(()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to archive room {}: {}", room.code, err);
+    }
+}
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                            "request-access" => {
-                                let data: RequestAccessData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+/// Builds the history entry for the session ending now. Rooms nobody joined
+/// are only archived when they were a scheduled meeting.
+async fn session_history(
+    db: &Arc<Database>,
+    room: &Room,
+    reason: &str,
+) -> mongodb::error::Result<Option<RoomHistory>> {
+    let ended_at = DateTime::now();
+    let since = session_boundary(db, room).await?;
+
+    let records = Database::get_attendance(db.clone(), &room.code, since, None, None).await?;
+    let meeting = Database::get_latest_started_meeting(db.clone(), &room.code, ended_at)
+        .await?
+        .filter(|meeting| since.is_none_or(|since| meeting.end_time >= since));
+    if records.is_empty() && meeting.is_none() {
+        return Ok(None);
+    }
 
-                                let response: RequestAccessResponse = RequestAccessResponse {
-                                    message_type: "request-access".to_string(),
-                                    user_id: data.from,
-                                    username: data.username,
-                                };
+    let started_at = records
+        .iter()
+        .filter_map(|record| record.joined_at)
+        .min()
+        .or(since)
+        .unwrap_or(ended_at);
+
+    Ok(Some(RoomHistory {
+        _id: None,
+        room_code: room.code.clone(),
+        host_id: room.host_id,
+        meeting_id: meeting.as_ref().and_then(|meeting| meeting._id),
+        series_id: meeting.as_ref().and_then(|meeting| meeting.series_id),
+        title: meeting.map(|meeting| meeting.title),
+        started_at,
+        ended_at,
+        duration_secs: (ended_at.timestamp_millis() - started_at.timestamp_millis()) / 1000,
+        end_reason: reason.to_string(),
+        peak_participants: peak_attendance(&records) as u32,
+        attendance: summarize_attendance(&records, since, Some(ended_at)),
+    }))
+}
 
-                                let response_text = serde_json::to_string(&response).unwrap();
+/// When the current session may have started at the earliest: the end of the
+/// last archived session, or the room's creation.
+pub async fn session_boundary(
+    db: &Arc<Database>,
+    room: &Room,
+) -> mongodb::error::Result<Option<DateTime>> {
+    let last_end = Database::get_last_archived_end(db.clone(), &room.code).await?;
+    Ok(room.created_at.max(last_end))
+}
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+/// The first join of the current session, if anyone has joined yet.
+async fn session_started_at(
+    db: &Arc<Database>,
+    room: &Room,
+) -> mongodb::error::Result<Option<DateTime>> {
+    let since = session_boundary(db, room).await?;
+    let records = Database::get_attendance(db.clone(), &room.code, since, None, None).await?;
+    Ok(records.iter().filter_map(|record| record.joined_at).min())
+}
 
-                                if let Some(sender_id) = user_sockets.get(&data.to) {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(sender_id).cloned()
-                                    };
+async fn send_room_ended(ws_state: &AppState, room: &Room, reason: &str) {
+    ws_state.room_states.lock().await.remove(&room.code);
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
-                                    }
-                                }
-                            }
+    let response = RoomEnded {
+        message_type: "room-ended".to_string(),
+        code: room.code.clone(),
+        reason: reason.to_string(),
+    };
+    let response_text = serde_json::to_string(&response).unwrap();
+    send_to_room(ws_state, room, &response_text).await;
 
-                            "allowed-access" => {
-                                let data: AccessData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+    let mut guests = ws_state.guests.lock().await;
+    for participant in &room.participants_id {
+        guests.remove(participant);
+    }
+}
 
-                                let room: Room = match Database::get_room_by_code(
-                                    db.clone(),
-                                    &data.code,
-                                )
-                                .await
-                                {
-                                    Ok(Some(room)) => room,
-                                    _ => continue,
-                                };
+pub async fn send_to_room(ws_state: &AppState, room: &Room, text: &str) {
+    for participant in &room.participants_id {
+        send_to_user(ws_state, participant, text).await;
+    }
+    send_to_user(ws_state, &room.host_id, text).await;
+}
 
-                                let response: AccessResponse = AccessResponse {
-                                    message_type: "allowed-access".to_string(),
-                                    user_id: data.user_id,
-                                    username: data.username,
-                                };
-                                let response_text = serde_json::to_string(&response).unwrap();
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+/// Sends news about a message to everyone who can see it: the sender and
+/// recipients of a private message, or the whole room.
+async fn send_to_audience(ws_state: &AppState, room: &Room, message: &ChatMessage, text: &str) {
+    if !message.is_private() {
+        send_to_room(ws_state, room, text).await;
+        return;
+    }
+    for user_id in message
+        .recipients
+        .iter()
+        .chain(std::iter::once(&message.sender_id))
+    {
+        send_to_user(ws_state, user_id, text).await;
+    }
+}
 
-                                for participant in &room.participants_id {
-                                    if let Some(sender_id) = user_sockets.get(&participant) {
-                                        let sender_arc = {
-                                            let sockets = ws_state.sockets.lock().await;
-                                            sockets.get(sender_id).cloned()
-                                        };
+/// Number of room members (host included) that currently hold an open socket.
+pub async fn live_member_count(ws_state: &AppState, room: &Room) -> usize {
+    let user_sockets = ws_state.user_sockets.lock().await;
+    let sockets = ws_state.sockets.lock().await;
+
+    std::iter::once(&room.host_id)
+        .chain(room.participants_id.iter())
+        .filter(|id| {
+            user_sockets
+                .get(id)
+                .is_some_and(|socket_id| sockets.contains_key(socket_id))
+        })
+        .count()
+}
 
-                                        if let Some(sender_arc) = sender_arc {
-                                            let mut sender = sender_arc.lock().await;
-                                            if let Err(err) = sender
-                                                .send(Message::Text(response_text.clone().into()))
-                                                .await
-                                            {
-                                                eprintln!(
-                                                    "Failed to send message to user {}: {}",
-                                                    sender_id, err
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::room_model::{BanEntry, RoomSettings};
+
+    fn room(participants: usize) -> Room {
+        Room {
+            _id: None,
+            host_id: ObjectId::new(),
+            code: "123456".to_string(),
+            participants_id: (0..participants).map(|_| ObjectId::new()).collect(),
+            created_at: None,
+            settings: RoomSettings::default(),
+            lobby: vec![],
+            roles: vec![],
+            banned: vec![],
+            invites: vec![],
+            parent_code: None,
+            breakout_name: None,
+            breakouts_end_at: None,
+        }
+    }
 
-                            "rejected-access" => {
-                                let data: AccessData =
-                                    match serde_json::from_value(json["data"].clone()) {
-                                        Ok(d) => d,
-                                        Err(_) => {
-                                            println!("err");
-                                            continue;
-                                        }
-                                    };
+    fn plan(max_participants: Option<u32>) -> Plan {
+        Plan {
+            name: "test".to_string(),
+            max_meeting_mins: None,
+            max_concurrent_rooms: None,
+            max_participants,
+            remote_control: true,
+        }
+    }
 
-                                let response: AccessResponse = AccessResponse {
-                                    message_type: "rejected-access".to_string(),
-                                    user_id: data.user_id,
-                                    username: data.username,
-                                };
-                                let response_text = serde_json::to_string(&response).unwrap();
+    #[test]
+    fn open_room_admits_anyone() {
+        let room = room(2);
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), ObjectId::new(), None),
+            None
+        );
+    }
+
+    #[test]
+    fn banned_users_are_denied_first() {
+        let mut room = room(0);
+        let user_id = ObjectId::new();
+        room.settings.locked = true;
+        room.banned.push(BanEntry {
+            user_id,
+            banned_by: room.host_id,
+            reason: None,
+            banned_at: DateTime::now(),
+        });
+
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), user_id, None),
+            Some("banned")
+        );
+    }
 
-                                let user_sockets = ws_state.user_sockets.lock().await.clone();
+    #[test]
+    fn locked_room_is_denied() {
+        let mut room = room(0);
+        room.settings.locked = true;
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), ObjectId::new(), None),
+            Some("locked")
+        );
+    }
 
-                                if let Some(sender_id) = user_sockets.get(&data.user_id) {
-                                    let sender_arc = {
-                                        let sockets = ws_state.sockets.lock().await;
-                                        sockets.get(sender_id).cloned()
-                                    };
+    #[test]
+    fn room_cap_counts_the_host() {
+        let mut room = room(2);
+        room.settings.max_participants = Some(4);
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), ObjectId::new(), None),
+            None
+        );
+
+        room.participants_id.push(ObjectId::new());
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), ObjectId::new(), None),
+            Some("room-full")
+        );
+    }
 
-                                    if let Some(sender_arc) = sender_arc {
-                                        let mut sender = sender_arc.lock().await;
-                                        if let Err(err) =
-                                            sender.send(Message::Text(response_text.into())).await
-                                        {
-                                            eprintln!(
-                                                "Failed to send message to user {}: {}",
-                                                sender_id, err
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                            _ => continue,
-                        }
-                    }
-                }
-            }
-            _ => continue,
-        }
+    #[test]
+    fn the_smaller_of_room_and_plan_cap_applies() {
+        let mut room = room(2);
+        room.settings.max_participants = Some(10);
+        assert_eq!(
+            join_denial_reason(&room, &plan(Some(3)), ObjectId::new(), None),
+            Some("room-full")
+        );
+
+        room.settings.max_participants = Some(3);
+        assert_eq!(
+            join_denial_reason(&room, &plan(Some(10)), ObjectId::new(), None),
+            Some("room-full")
+        );
+    }
+
+    #[test]
+    fn members_rejoining_a_full_room_are_not_counted_twice() {
+        let mut room = room(2);
+        room.settings.max_participants = Some(3);
+        let member = room.participants_id[0];
+        assert_eq!(join_denial_reason(&room, &plan(None), member, None), None);
+    }
+
+    #[test]
+    fn password_must_match() {
+        let mut room = room(0);
+        room.settings.password_hash = Some(bcrypt::hash("secret", 4).unwrap());
+        let user_id = ObjectId::new();
+
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), user_id, None),
+            Some("invalid-password")
+        );
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), user_id, Some("wrong")),
+            Some("invalid-password")
+        );
+        assert_eq!(
+            join_denial_reason(&room, &plan(None), user_id, Some("secret")),
+            None
+        );
+    }
+
+    #[test]
+    fn admitting_skips_the_lock_and_password_but_not_bans_or_caps() {
+        let mut room = room(2);
+        room.settings.locked = true;
+        room.settings.password_hash = Some(bcrypt::hash("secret", 4).unwrap());
+        assert_eq!(
+            admit_denial_reason(&room, &plan(None), ObjectId::new()),
+            None
+        );
+
+        room.settings.max_participants = Some(3);
+        assert_eq!(
+            admit_denial_reason(&room, &plan(None), ObjectId::new()),
+            Some("room-full")
+        );
+
+        let user_id = ObjectId::new();
+        room.banned.push(BanEntry {
+            user_id,
+            banned_by: room.host_id,
+            reason: None,
+            banned_at: DateTime::now(),
+        });
+        assert_eq!(
+            admit_denial_reason(&room, &plan(None), user_id),
+            Some("banned")
+        );
     }
 }
@@ -264,7 +264,7 @@ function Room() {
       await peersRef.current[userId.$oid].setLocalDescription(offer);
       sendMessage("offer", {
         item: offer,
-        user_id: id,
+        code,
         to: userId,
       });
     } catch (err) {
@@ -359,7 +359,7 @@ function Room() {
           );
           sendMessage("offer", {
             item: offer,
-            user_id: user.id,
+            code,
             to: participant.id,
           });
         }
@@ -482,7 +482,7 @@ function Room() {
         await peersRef.current[host.id.$oid].setLocalDescription(offer);
         sendMessage("offer", {
           item: offer,
-          user_id: user.id,
+          code,
           to: host.id,
         });
       }
@@ -503,7 +503,7 @@ function Room() {
           );
           sendMessage("offer", {
             item: offer,
-            user_id: user.id,
+            code,
             to: participant.id,
           });
         }
@@ -615,7 +615,7 @@ function Room() {
           await peersRef.current[host.id.$oid].setLocalDescription(offer);
           sendMessage("offer", {
             item: offer,
-            user_id: user.id,
+            code,
             to: host.id,
           });
         }
@@ -636,7 +636,7 @@ function Room() {
             );
             sendMessage("offer", {
               item: offer,
-              user_id: user.id,
+              code,
               to: participant.id,
             });
           }
@@ -711,7 +711,7 @@ function Room() {
       if (event.candidate) {
         sendMessage("ice-candidate", {
           item: event.candidate,
-          user_id: id,
+          code,
           to: participantId,
         });
       }
@@ -768,7 +768,7 @@ function Room() {
 
     const answer = await peersRef.current[senderId.$oid].createAnswer();
     await peersRef.current[senderId.$oid].setLocalDescription(answer);
-    sendMessage("answer", { item: answer, user_id: userId, to: senderId });
+    sendMessage("answer", { item: answer, code, to: senderId });
   };
 
   const handleScreenShareAnswer = async (answer, senderId) => {
@@ -782,7 +782,7 @@ function Room() {
 
     let x = (e.clientX - rect.left) / rect.width;
     let y = (e.clientY - rect.top) / rect.height;
-    sendMessage("mouse-move", { x, y, to: id });
+    sendMessage("mouse-move", { x, y, to: id, code });
   };
 
   const handleKeyPress = (e, id) => {
@@ -790,11 +790,11 @@ function Room() {
       e.preventDefault();
     }
     console.log(e.key);
-    sendMessage("key-press", { key: e.key, to: id });
+    sendMessage("key-press", { key: e.key, to: id, code });
   };
 
   const handleClick = (e, id) => {
-    sendMessage("mouse-click", { to: id });
+    sendMessage("mouse-click", { to: id, code });
   };
 
   const toggleMessages = () => {
@@ -819,6 +819,7 @@ function Room() {
       to: host.id,
       from: user.id,
       username: user.username,
+      code,
     });
   };
 